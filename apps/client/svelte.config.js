@@ -8,7 +8,9 @@ const config = {
 	preprocess: vitePreprocess(),
 	kit: { 
 		adapter: adapter({
-			serveAssets: false
+			serveAssets: false,
+			// Emit .br and .gz variants for the Rust server to pick from
+			precompress: true
 		})
 	}
 };
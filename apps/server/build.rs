@@ -39,6 +39,7 @@ fn main() {
     }
 
     println!("cargo:rerun-if-changed=../client/src");
+    println!("cargo:rerun-if-changed=../client/static");
     println!("cargo:rerun-if-changed=../client/package.json");
     println!("cargo:rerun-if-changed=../client/vite.config.ts");
     println!("cargo:rerun-if-changed=../client/svelte.config.js");
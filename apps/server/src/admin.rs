@@ -0,0 +1,326 @@
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, Request},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Extension, Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tracing::{info, warn};
+
+use crate::{
+    api::{ApiError, ApiJson, ApiResult},
+    health, AppState,
+};
+
+/// Path prefix the admin endpoints are mounted under.
+pub const ADMIN_PREFIX: &str = "/_admin";
+
+/// Environment variable holding the bearer token required by the admin endpoints.
+const ADMIN_TOKEN_ENV: &str = "ADMIN_TOKEN";
+
+#[derive(Deserialize)]
+struct PurgePathRequest {
+    path: String,
+}
+
+#[derive(Deserialize)]
+struct PurgePrefixRequest {
+    prefix: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum PurgeScope {
+    All,
+    Path,
+    Prefix,
+}
+
+/// JSON body describing what a purge request invalidated
+#[derive(Serialize)]
+struct PurgeResponse {
+    scope: PurgeScope,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pattern: Option<String>,
+}
+
+/// Creates the router for cache administration and the detailed status report,
+/// or `None` when no admin token is configured.
+///
+/// The routes are nested under [`ADMIN_PREFIX`] and every request must carry
+/// `Authorization: Bearer <ADMIN_TOKEN>`.
+pub fn create_admin_router() -> Option<Router> {
+    let token = match std::env::var(ADMIN_TOKEN_ENV) {
+        Ok(token) if !token.is_empty() => token,
+        _ => {
+            warn!("{} is not set, admin endpoints are disabled", ADMIN_TOKEN_ENV);
+            return None;
+        }
+    };
+
+    info!("Mounting admin endpoints under {}", ADMIN_PREFIX);
+    Some(admin_router(&token))
+}
+
+fn admin_router(token: &str) -> Router {
+    let routes = Router::new()
+        .route("/cache/refresh", post(refresh_all))
+        .route("/cache/purge", post(purge_path))
+        .route("/cache/purge-prefix", post(purge_prefix))
+        .route("/status", get(health::status))
+        .route_layer(middleware::from_fn_with_state(
+            Arc::<str>::from(token),
+            require_bearer_token,
+        ));
+
+    Router::new().nest(ADMIN_PREFIX, routes)
+}
+
+async fn require_bearer_token(
+    State(token): State<Arc<str>>,
+    req: Request<Body>,
+    next: Next,
+) -> Response {
+    let provided = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "));
+
+    match provided {
+        Some(provided) if constant_time_eq(provided.as_bytes(), token.as_bytes()) => {
+            next.run(req).await
+        }
+        _ => {
+            warn!("Unauthorized admin request to {}", req.uri().path());
+            (
+                [(header::WWW_AUTHENTICATE, "Bearer")],
+                ApiError::Unauthorized,
+            )
+                .into_response()
+        }
+    }
+}
+
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+async fn refresh_all(Extension(state): Extension<Arc<AppState>>) -> Json<PurgeResponse> {
+    state.refresh_frontend.trigger();
+    info!("Full cache refresh triggered via admin endpoint");
+
+    Json(PurgeResponse {
+        scope: PurgeScope::All,
+        target: None,
+        pattern: None,
+    })
+}
+
+async fn purge_path(
+    Extension(state): Extension<Arc<AppState>>,
+    ApiJson(body): ApiJson<PurgePathRequest>,
+) -> ApiResult<PurgeResponse> {
+    if !body.path.starts_with('/') {
+        return Err(ApiError::BadRequest("path must start with '/'".to_string()));
+    }
+    // phantom-frame treats '*' as a wildcard, which would purge more than this one path
+    if body.path.contains('*') {
+        return Err(ApiError::BadRequest(
+            "path must not contain '*', use purge-prefix to purge several paths".to_string(),
+        ));
+    }
+
+    let pattern = state.config.proxy.cache_key_pattern(&body.path);
+    state.refresh_frontend.trigger_by_key_match(&pattern);
+    info!("Cache purge triggered for path {}", body.path);
+
+    Ok(Json(PurgeResponse {
+        scope: PurgeScope::Path,
+        target: Some(body.path),
+        pattern: Some(pattern),
+    }))
+}
+
+async fn purge_prefix(
+    Extension(state): Extension<Arc<AppState>>,
+    ApiJson(body): ApiJson<PurgePrefixRequest>,
+) -> ApiResult<PurgeResponse> {
+    if !body.prefix.starts_with('/') {
+        return Err(ApiError::BadRequest("prefix must start with '/'".to_string()));
+    }
+    if body.prefix.contains('*') {
+        return Err(ApiError::BadRequest("prefix must not contain '*'".to_string()));
+    }
+
+    let pattern = state
+        .config
+        .proxy
+        .cache_key_pattern(&format!("{}*", body.prefix));
+    state.refresh_frontend.trigger_by_key_match(&pattern);
+    info!("Cache purge triggered for prefix {}", body.prefix);
+
+    Ok(Json(PurgeResponse {
+        scope: PurgeScope::Prefix,
+        target: Some(body.prefix),
+        pattern: Some(pattern),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::Config,
+        embed::{FrontendAddress, FrontendState, FrontendStatus},
+        health::Health,
+    };
+    use axum::http::StatusCode;
+    use phantom_frame::cache::{RefreshMessage, RefreshTrigger};
+    use tokio::sync::{broadcast, watch};
+    use tower::ServiceExt;
+
+    const TOKEN: &str = "secret";
+
+    fn app() -> (Router, broadcast::Receiver<RefreshMessage>) {
+        let refresh_frontend = RefreshTrigger::new();
+        let refreshes = refresh_frontend.subscribe();
+        let (_, status) = watch::channel(FrontendStatus {
+            state: FrontendState::Ready,
+            restarts: 0,
+        });
+        let frontend_addr = FrontendAddress::Tcp {
+            host: "127.0.0.1".into(),
+            port: 0,
+        };
+        let state = Arc::new(AppState {
+            health: Health::new(status, frontend_addr, &refresh_frontend),
+            refresh_frontend,
+            config: Arc::new(Config::default()),
+            metrics: None,
+        });
+
+        (admin_router(TOKEN).layer(Extension(state)), refreshes)
+    }
+
+    async fn post(
+        app: Router,
+        path: &str,
+        token: Option<&str>,
+        body: &str,
+    ) -> (StatusCode, String) {
+        let mut request = Request::post(format!("{}{}", ADMIN_PREFIX, path))
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(token) = token {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", token));
+        }
+        let request = request.body(Body::from(body.to_string())).unwrap();
+
+        let response = app.oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_and_wrong_tokens() {
+        for token in [None, Some("wrong"), Some("secre")] {
+            let (app, mut refreshes) = app();
+            let (status, body) = post(app, "/cache/refresh", token, "").await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED, "{:?}", token);
+            assert!(body.contains(r#""code":"unauthorized""#), "{}", body);
+            assert!(refreshes.try_recv().is_err(), "nothing was purged");
+        }
+    }
+
+    #[tokio::test]
+    async fn refreshes_everything() {
+        let (app, mut refreshes) = app();
+        let (status, body) = post(app, "/cache/refresh", Some(TOKEN), "").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, r#"{"scope":"all"}"#);
+        assert!(matches!(refreshes.try_recv(), Ok(RefreshMessage::All)));
+    }
+
+    #[tokio::test]
+    async fn purges_one_path() {
+        let (app, mut refreshes) = app();
+        let (status, body) = post(app, "/cache/purge", Some(TOKEN), r#"{"path":"/blog"}"#).await;
+        assert_eq!(status, StatusCode::OK);
+        let expected = r#"{"scope":"path","target":"/blog","pattern":"*::/blog"}"#;
+        assert_eq!(body, expected);
+        match refreshes.try_recv() {
+            Ok(RefreshMessage::Pattern(pattern)) => assert_eq!(pattern, "*::/blog"),
+            other => panic!("unexpected refresh {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn purges_a_prefix() {
+        let (app, mut refreshes) = app();
+        let body = r#"{"prefix":"/blog/"}"#;
+        let (status, _) = post(app, "/cache/purge-prefix", Some(TOKEN), body).await;
+        assert_eq!(status, StatusCode::OK);
+        match refreshes.try_recv() {
+            Ok(RefreshMessage::Pattern(pattern)) => assert_eq!(pattern, "*::/blog/*"),
+            other => panic!("unexpected refresh {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_wildcards_in_paths() {
+        let (app, mut refreshes) = app();
+        let (status, body) = post(app, "/cache/purge", Some(TOKEN), r#"{"path":"/*"}"#).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body.contains("must not contain '*'"), "{}", body);
+        assert!(refreshes.try_recv().is_err(), "nothing was purged");
+    }
+
+    #[tokio::test]
+    async fn answers_bad_bodies_with_json_errors() {
+        for body in ["not json", r#"{"prefix":"/blog"}"#, r#"{"path":"blog"}"#] {
+            let (app, mut refreshes) = app();
+            let (status, response) = post(app, "/cache/purge", Some(TOKEN), body).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", body);
+            let response: serde_json::Value = serde_json::from_str(&response).unwrap();
+            assert_eq!(response["error"]["code"], "bad_request", "{}", body);
+            assert!(response["error"]["message"].is_string());
+            assert!(refreshes.try_recv().is_err(), "nothing was purged");
+        }
+    }
+
+    #[tokio::test]
+    async fn reports_status_details_behind_the_token() {
+        let (app, _) = app();
+        let request = Request::get(format!("{}/status", ADMIN_PREFIX))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let request = Request::get(format!("{}/status", ADMIN_PREFIX))
+            .header(header::AUTHORIZATION, format!("Bearer {}", TOKEN))
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        // Nothing listens on port 0, so the frontend is reported unreachable
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["status"], "not_ready");
+        assert_eq!(body["frontend"]["state"], "ready");
+        assert_eq!(body["frontend"]["address"], "127.0.0.1:0");
+        assert_eq!(body["frontend"]["reachable"], false);
+        assert_eq!(body["cache"]["key"], "{method}::{path}");
+    }
+}
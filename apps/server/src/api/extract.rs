@@ -0,0 +1,22 @@
+use axum::{
+    extract::{rejection::JsonRejection, FromRequest, Request},
+    Json,
+};
+
+use super::ApiError;
+
+/// [`Json`] extractor whose rejections are rendered as an [`ApiError`] instead of plain text
+pub struct ApiJson<T>(pub T);
+
+impl<T, S> FromRequest<S> for ApiJson<T>
+where
+    Json<T>: FromRequest<S, Rejection = JsonRejection>,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state).await?;
+        Ok(Self(value))
+    }
+}
@@ -0,0 +1,39 @@
+use axum::{routing::get, Json, Router};
+use serde::Serialize;
+use tracing::info;
+
+mod error;
+mod extract;
+
+pub use error::{ApiError, ApiResult};
+pub use extract::ApiJson;
+
+/// Creates the Rust API router nested under `prefix`.
+///
+/// Handlers can extract `Extension<Arc<AppState>>`. Unknown paths under the prefix
+/// answer with a JSON 404 instead of falling through to the SSR proxy.
+pub fn create_api_router(prefix: &str) -> Router {
+    info!("Mounting API routes under {}", prefix);
+    let routes = Router::new()
+        .route("/version", get(version))
+        .fallback(not_found);
+
+    Router::new().nest(prefix, routes)
+}
+
+#[derive(Serialize)]
+struct VersionResponse {
+    name: &'static str,
+    version: &'static str,
+}
+
+async fn version() -> ApiResult<VersionResponse> {
+    Ok(Json(VersionResponse {
+        name: env!("WORKSPACE_NAME"),
+        version: env!("CARGO_PKG_VERSION"),
+    }))
+}
+
+async fn not_found() -> ApiError {
+    ApiError::NotFound
+}
@@ -0,0 +1,1233 @@
+use anyhow::{Context, Result};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::listen::{ListenAddr, ListenOptions};
+use crate::upstream::Upstream;
+
+/// Config file read from the working directory when `--config` is not given
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+/// Methods that may appear in `proxy.exclude_methods`
+const HTTP_METHODS: &[&str] = &[
+    "GET", "HEAD", "POST", "PUT", "DELETE", "PATCH", "OPTIONS", "CONNECT", "TRACE",
+];
+
+/// Placeholders understood by `proxy.cache_key`
+const CACHE_KEY_PLACEHOLDERS: &[&str] = &["method", "path", "query"];
+
+/// Serves the SvelteKit frontend behind a caching proxy.
+///
+/// Settings come from the config file, then environment variables, then these flags;
+/// later sources win.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Cli {
+    /// TOML config file to load [env: CONFIG_FILE] [default: config.toml, if present]
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<PathBuf>,
+
+    /// Print the effective configuration as TOML and exit
+    #[arg(long)]
+    pub print_config: bool,
+
+    /// Address to listen on: IP, IP:PORT, [IPv6]:PORT or unix:/path; repeatable [env: LISTEN, comma separated]
+    #[arg(long, value_name = "ADDR", value_delimiter = ',')]
+    pub listen: Option<Vec<ListenAddr>>,
+
+    /// Port for listen addresses that do not name one [env: PORT]
+    #[arg(long)]
+    pub port: Option<u16>,
+
+    /// Keep IPv6 wildcard listeners from also accepting IPv4 [env: IPV6_ONLY]
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    pub ipv6_only: Option<bool>,
+
+    /// Octal permissions for Unix socket files [env: UNIX_SOCKET_MODE]
+    #[arg(long, value_name = "MODE")]
+    pub unix_socket_mode: Option<String>,
+
+    /// PEM certificate chain; enables HTTPS on TCP listeners [env: TLS_CERT]
+    #[arg(long, value_name = "PATH")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM private key for --tls-cert [env: TLS_KEY]
+    #[arg(long, value_name = "PATH")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Path of the Prometheus metrics endpoint [env: METRICS_PATH]
+    #[arg(long, value_name = "PATH")]
+    pub metrics_path: Option<String>,
+
+    /// Serve and collect metrics [env: METRICS_ENABLED]
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    pub metrics: Option<bool>,
+
+    /// Log output: text or json [env: LOG_FORMAT]
+    #[arg(long, value_name = "FORMAT")]
+    pub log_format: Option<LogFormat>,
+
+    /// OTLP/HTTP collector to export traces to, e.g. http://localhost:4318 [env: OTEL_EXPORTER_OTLP_ENDPOINT]
+    #[arg(long, value_name = "URL")]
+    pub otlp_endpoint: Option<String>,
+
+    /// Seconds to drain in-flight requests on shutdown [env: SHUTDOWN_TIMEOUT]
+    #[arg(long, value_name = "SECS")]
+    pub shutdown_timeout: Option<u64>,
+
+    /// Path prefix the Rust API is mounted under [env: API_PREFIX]
+    #[arg(long, value_name = "PREFIX")]
+    pub api_prefix: Option<String>,
+
+    /// Cache key template using {method}, {path} and {query} [env: CACHE_KEY]
+    #[arg(long, value_name = "TEMPLATE")]
+    pub cache_key: Option<String>,
+
+    /// Comma separated HTTP methods that bypass the cache [env: CACHE_EXCLUDE_METHODS]
+    #[arg(long, value_name = "METHODS", value_delimiter = ',')]
+    pub exclude_methods: Option<Vec<String>>,
+
+    /// Proxy to an already running frontend, http(s)://host:port or unix:/path/to.sock, instead of spawning one [env: FRONTEND_URL]
+    #[arg(long, value_name = "URL")]
+    pub frontend_url: Option<String>,
+
+    /// Fixed port for the production frontend [env: FRONTEND_PORT]
+    #[arg(long, value_name = "PORT")]
+    pub frontend_port: Option<u16>,
+
+    /// Range of ports to pick the production frontend's port from, e.g. 40000-40100 [env: FRONTEND_PORT_RANGE]
+    #[arg(long, value_name = "START-END")]
+    pub frontend_port_range: Option<PortRange>,
+
+    /// How the proxy reaches the production frontend: tcp (default) or unix [env: FRONTEND_TRANSPORT]
+    #[arg(long, value_name = "TRANSPORT")]
+    pub frontend_transport: Option<FrontendTransport>,
+
+    /// Port of the Vite dev server [env: FRONTEND_DEV_PORT]
+    #[arg(long, value_name = "PORT")]
+    pub dev_port: Option<u16>,
+
+    /// Seconds to wait for the frontend to become ready [env: FRONTEND_READY_TIMEOUT]
+    #[arg(long, value_name = "SECS")]
+    pub ready_timeout: Option<u64>,
+
+    /// Frontend path that must answer 2xx before it counts as ready [env: FRONTEND_READY_PATH]
+    #[arg(long, value_name = "PATH")]
+    pub ready_path: Option<String>,
+
+    /// Tracing target for the frontend's output [env: FRONTEND_LOG_TARGET]
+    #[arg(long, value_name = "TARGET")]
+    pub frontend_log_target: Option<String>,
+
+    /// Directory to extract the embedded frontend into instead of the system temp dir [env: FRONTEND_EXTRACT_DIR]
+    #[arg(long, value_name = "DIR")]
+    pub extract_dir: Option<PathBuf>,
+
+    /// Comma separated environment variables the frontend inherits; all when unset [env: FRONTEND_ENV_ALLOWLIST]
+    #[arg(long, value_name = "NAMES", value_delimiter = ',')]
+    pub frontend_env_allowlist: Option<Vec<String>>,
+
+    /// Memory limit for the frontend (RLIMIT_DATA) in MiB [env: FRONTEND_MEMORY_LIMIT_MB]
+    #[arg(long, value_name = "MIB")]
+    pub frontend_memory_limit_mb: Option<u64>,
+
+    /// Limit on the frontend's open file descriptors [env: FRONTEND_MAX_OPEN_FILES]
+    #[arg(long, value_name = "COUNT")]
+    pub frontend_max_open_files: Option<u64>,
+
+    /// Limit on the frontend's total CPU time in seconds [env: FRONTEND_CPU_LIMIT_SECS]
+    #[arg(long, value_name = "SECS")]
+    pub frontend_cpu_limit_secs: Option<u64>,
+
+    /// Run the frontend in its own process group, on by default on Unix [env: FRONTEND_PROCESS_GROUP]
+    #[arg(long, value_name = "BOOL", num_args = 0..=1, default_missing_value = "true")]
+    pub frontend_process_group: Option<bool>,
+
+    /// User, by name or uid, to run the frontend as when the server runs as root [env: FRONTEND_USER]
+    #[arg(long, value_name = "USER")]
+    pub frontend_user: Option<String>,
+
+    /// Group, by name or gid, to run the frontend as; defaults to the user's primary group [env: FRONTEND_GROUP]
+    #[arg(long, value_name = "GROUP")]
+    pub frontend_group: Option<String>,
+
+    /// Extra MIME mapping for static assets, repeatable [env: ASSET_MIME_TYPES, comma separated]
+    #[arg(long = "mime-type", value_name = "EXT=TYPE")]
+    pub mime_types: Vec<String>,
+}
+
+/// Effective server configuration: defaults, then the config file, then environment variables, then CLI flags.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub tls: TlsConfig,
+    pub proxy: ProxyConfig,
+    pub frontend: FrontendConfig,
+    pub assets: AssetsConfig,
+    pub metrics: MetricsConfig,
+    pub log: LogConfig,
+    pub telemetry: TelemetryConfig,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub listen: Vec<ListenAddr>,
+    /// Port used by listen addresses without an explicit one
+    pub port: u16,
+    /// When false, `::` listeners are dual-stack and also accept IPv4 connections
+    pub ipv6_only: bool,
+    /// Octal permission bits for Unix socket files, e.g. `"660"`
+    pub unix_socket_mode: String,
+    pub shutdown_timeout_secs: u64,
+    pub api_prefix: String,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            listen: vec![ListenAddr::Tcp {
+                ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                port: None,
+            }],
+            port: 3030,
+            ipv6_only: false,
+            unix_socket_mode: "660".to_string(),
+            shutdown_timeout_secs: 10,
+            api_prefix: "/api".to_string(),
+        }
+    }
+}
+
+impl ServerConfig {
+    pub fn shutdown_timeout(&self) -> Duration {
+        Duration::from_secs(self.shutdown_timeout_secs)
+    }
+
+    pub fn listen_options(&self) -> ListenOptions {
+        ListenOptions {
+            default_port: self.port,
+            ipv6_only: self.ipv6_only,
+            unix_socket_mode: parse_mode(&self.unix_socket_mode).unwrap_or(0o660),
+        }
+    }
+}
+
+/// HTTPS for TCP listeners; Unix socket listeners always speak plain HTTP
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TlsConfig {
+    /// PEM certificate chain, leaf certificate first
+    pub cert: Option<PathBuf>,
+    /// PEM private key (PKCS#8, PKCS#1 or SEC1)
+    pub key: Option<PathBuf>,
+    /// How often the files are checked for changes
+    pub reload_interval_secs: u64,
+}
+
+impl Default for TlsConfig {
+    fn default() -> Self {
+        Self {
+            cert: None,
+            key: None,
+            reload_interval_secs: 10,
+        }
+    }
+}
+
+impl TlsConfig {
+    /// Certificate and key paths, when TLS is enabled
+    pub fn paths(&self) -> Option<(&Path, &Path)> {
+        Some((self.cert.as_deref()?, self.key.as_deref()?))
+    }
+
+    pub fn reload_interval(&self) -> Duration {
+        Duration::from_secs(self.reload_interval_secs)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProxyConfig {
+    /// Template for cache keys; `{path}` is required, `{method}` and `{query}` are optional
+    pub cache_key: String,
+    /// Requests with these methods are never cached
+    pub exclude_methods: Vec<String>,
+    /// Additional phantom-frame exclude patterns, e.g. `/account/*` or `GET /preview/*`
+    pub exclude_paths: Vec<String>,
+}
+
+impl Default for ProxyConfig {
+    fn default() -> Self {
+        Self {
+            cache_key: "{method}::{path}".to_string(),
+            exclude_methods: ["POST", "PUT", "DELETE", "PATCH"]
+                .map(String::from)
+                .to_vec(),
+            exclude_paths: vec![],
+        }
+    }
+}
+
+impl ProxyConfig {
+    /// Renders the cache key for a request
+    pub fn render_cache_key(&self, method: &str, path: &str, query: &str) -> String {
+        render_template(&self.cache_key, |name| match name {
+            "method" => method,
+            "path" => path,
+            _ => query,
+        })
+    }
+
+    /// Builds a refresh pattern matching every cached key whose path matches `path_pattern`
+    pub fn cache_key_pattern(&self, path_pattern: &str) -> String {
+        render_template(&self.cache_key, |name| match name {
+            "path" => path_pattern,
+            _ => "*",
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FrontendConfig {
+    /// Proxy to this URL, or to `unix:/path/to.sock`, instead of spawning the frontend
+    pub url: Option<String>,
+    /// Fixed port for the production frontend; a free port is picked when unset
+    pub port: Option<u16>,
+    /// Pick the production frontend's port from this range instead of any free port.
+    /// Either way the port is only checked, not reserved, until the frontend binds it
+    pub port_range: Option<PortRange>,
+    /// `unix` runs the production frontend on a socket in a private directory instead of a port.
+    /// Opt-in, because the frontend has to listen on `SOCKET_PATH` for it to work
+    pub transport: FrontendTransport,
+    pub dev_port: u16,
+    pub ready_timeout_secs: u64,
+    /// Path polled over HTTP during startup until it answers 2xx
+    pub ready_path: String,
+    /// Tracing target for the frontend's output; defaults to `frontend`, or `dev-frontend` for Vite
+    pub log_target: Option<String>,
+    /// Where embedded frontend files are extracted, in a private per-user subdirectory.
+    /// Defaults to the system temp dir; must allow executables when the compiled client
+    /// cannot be run from memory
+    pub extract_dir: Option<PathBuf>,
+    pub sandbox: SandboxConfig,
+}
+
+impl Default for FrontendConfig {
+    fn default() -> Self {
+        Self {
+            url: None,
+            port: None,
+            port_range: None,
+            transport: FrontendTransport::Tcp,
+            dev_port: 5173,
+            ready_timeout_secs: 30,
+            ready_path: "/".to_string(),
+            log_target: None,
+            extract_dir: None,
+            sandbox: SandboxConfig::default(),
+        }
+    }
+}
+
+impl FrontendConfig {
+    pub fn ready_timeout(&self) -> Duration {
+        Duration::from_secs(self.ready_timeout_secs)
+    }
+}
+
+/// Inclusive range of TCP ports, written `START-END`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct PortRange {
+    pub start: u16,
+    pub end: u16,
+}
+
+impl FromStr for PortRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parsed = s
+            .split_once('-')
+            .and_then(|(start, end)| Some((start.trim().parse().ok()?, end.trim().parse().ok()?)));
+        match parsed {
+            Some((start, end)) => Ok(PortRange { start, end }),
+            None => Err(format!("expected START-END, got {:?}", s)),
+        }
+    }
+}
+
+impl TryFrom<String> for PortRange {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<PortRange> for String {
+    fn from(range: PortRange) -> Self {
+        format!("{}-{}", range.start, range.end)
+    }
+}
+
+/// Restrictions for the spawned frontend; only the process group is on by default.
+/// The Vite dev server only gets the process group, since the other restrictions break it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SandboxConfig {
+    /// Environment variables the frontend inherits; all of them when unset.
+    /// Variables the launcher sets itself, such as `PORT`, are always passed
+    pub env: Option<Vec<String>>,
+    /// RLIMIT_DATA rather than RLIMIT_AS, because Bun reserves far more address space than it uses
+    pub memory_limit_mb: Option<u64>,
+    pub max_open_files: Option<u64>,
+    /// Total CPU time, after which the kernel kills the frontend and the supervisor restarts it
+    pub cpu_limit_secs: Option<u64>,
+    /// Own process group: terminal signals do not reach the frontend, and
+    /// stopping it also stops the processes it started
+    pub process_group: bool,
+    /// User to run the frontend as, by name or uid; only applies when the server runs as root
+    pub user: Option<String>,
+    /// Group to run the frontend as, by name or gid; defaults to the user's primary group
+    pub group: Option<String>,
+}
+
+impl Default for SandboxConfig {
+    fn default() -> Self {
+        Self {
+            env: None,
+            memory_limit_mb: None,
+            max_open_files: None,
+            cpu_limit_secs: None,
+            process_group: cfg!(unix),
+            user: None,
+            group: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FrontendTransport {
+    /// A loopback TCP port
+    #[default]
+    Tcp,
+    /// A Unix socket, which the proxy opens through phantom-frame's connector hook
+    Unix,
+}
+
+impl FromStr for FrontendTransport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "tcp" => Ok(FrontendTransport::Tcp),
+            "unix" => Ok(FrontendTransport::Unix),
+            _ => Err(format!("expected tcp or unix, got {:?}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AssetsConfig {
+    /// Extension to MIME type mappings that override the built-in table
+    pub mime_types: BTreeMap<String, String>,
+}
+
+/// Prometheus metrics endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    pub enabled: bool,
+    pub path: String,
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            path: "/metrics".to_string(),
+        }
+    }
+}
+
+/// Log output; the level filter still comes from `RUST_LOG`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub format: LogFormat,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human readable, colored when writing to a terminal
+    #[default]
+    Text,
+    /// One JSON object per line, including the fields of the current span
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("expected text or json, got {:?}", s)),
+        }
+    }
+}
+
+/// OpenTelemetry trace export; only available when built with the `otel` feature
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TelemetryConfig {
+    /// Base URL of an OTLP/HTTP collector; spans are posted to `{endpoint}/v1/traces`
+    pub otlp_endpoint: Option<String>,
+    pub service_name: String,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            otlp_endpoint: None,
+            service_name: env!("CARGO_PKG_NAME").to_string(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads and validates the configuration for the given command line.
+    pub fn load(cli: &Cli) -> Result<Self> {
+        let path = match &cli.config {
+            Some(path) => Some(path.clone()),
+            None => env_value::<PathBuf>("CONFIG_FILE")?,
+        };
+        let mut config = match path {
+            Some(path) => Self::from_file(&path)?,
+            None if Path::new(DEFAULT_CONFIG_FILE).exists() => {
+                Self::from_file(Path::new(DEFAULT_CONFIG_FILE))?
+            }
+            None => Self::default(),
+        };
+
+        config.apply_env()?;
+        config.apply_cli(cli)?;
+        config.normalize();
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn from_file(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config file {:?}", path))?;
+        toml::from_str(&contents).with_context(|| format!("Invalid config file {:?}", path))
+    }
+
+    fn apply_env(&mut self) -> Result<()> {
+        if let Some(listen) = env_value::<String>("LISTEN")? {
+            self.server.listen = split_list(&listen)
+                .iter()
+                .map(|addr| addr.parse())
+                .collect::<Result<_>>()
+                .context("Invalid LISTEN")?;
+        }
+        if let Some(port) = env_value("PORT")? {
+            self.server.port = port;
+        }
+        if let Some(ipv6_only) = env_value("IPV6_ONLY")? {
+            self.server.ipv6_only = ipv6_only;
+        }
+        if let Some(mode) = env_value("UNIX_SOCKET_MODE")? {
+            self.server.unix_socket_mode = mode;
+        }
+        if let Some(cert) = env_value("TLS_CERT")? {
+            self.tls.cert = Some(cert);
+        }
+        if let Some(key) = env_value("TLS_KEY")? {
+            self.tls.key = Some(key);
+        }
+        if let Some(secs) = env_value("TLS_RELOAD_INTERVAL")? {
+            self.tls.reload_interval_secs = secs;
+        }
+        if let Some(secs) = env_value("SHUTDOWN_TIMEOUT")? {
+            self.server.shutdown_timeout_secs = secs;
+        }
+        if let Some(prefix) = env_value("API_PREFIX")? {
+            self.server.api_prefix = prefix;
+        }
+        if let Some(cache_key) = env_value("CACHE_KEY")? {
+            self.proxy.cache_key = cache_key;
+        }
+        if let Some(methods) = env_value::<String>("CACHE_EXCLUDE_METHODS")? {
+            self.proxy.exclude_methods = split_list(&methods);
+        }
+        if let Some(url) = env_value("FRONTEND_URL")? {
+            self.frontend.url = Some(url);
+        }
+        if let Some(port) = env_value("FRONTEND_PORT")? {
+            self.frontend.port = Some(port);
+        }
+        if let Some(range) = env_value("FRONTEND_PORT_RANGE")? {
+            self.frontend.port_range = Some(range);
+        }
+        if let Some(transport) = env_value("FRONTEND_TRANSPORT")? {
+            self.frontend.transport = transport;
+        }
+        if let Some(port) = env_value("FRONTEND_DEV_PORT")? {
+            self.frontend.dev_port = port;
+        }
+        if let Some(secs) = env_value("FRONTEND_READY_TIMEOUT")? {
+            self.frontend.ready_timeout_secs = secs;
+        }
+        if let Some(path) = env_value("FRONTEND_READY_PATH")? {
+            self.frontend.ready_path = path;
+        }
+        if let Some(target) = env_value("FRONTEND_LOG_TARGET")? {
+            self.frontend.log_target = Some(target);
+        }
+        if let Some(dir) = env_value("FRONTEND_EXTRACT_DIR")? {
+            self.frontend.extract_dir = Some(dir);
+        }
+        if let Some(names) = env_value::<String>("FRONTEND_ENV_ALLOWLIST")? {
+            self.frontend.sandbox.env = Some(split_list(&names));
+        }
+        if let Some(mb) = env_value("FRONTEND_MEMORY_LIMIT_MB")? {
+            self.frontend.sandbox.memory_limit_mb = Some(mb);
+        }
+        if let Some(count) = env_value("FRONTEND_MAX_OPEN_FILES")? {
+            self.frontend.sandbox.max_open_files = Some(count);
+        }
+        if let Some(secs) = env_value("FRONTEND_CPU_LIMIT_SECS")? {
+            self.frontend.sandbox.cpu_limit_secs = Some(secs);
+        }
+        if let Some(enabled) = env_value("FRONTEND_PROCESS_GROUP")? {
+            self.frontend.sandbox.process_group = enabled;
+        }
+        if let Some(user) = env_value("FRONTEND_USER")? {
+            self.frontend.sandbox.user = Some(user);
+        }
+        if let Some(group) = env_value("FRONTEND_GROUP")? {
+            self.frontend.sandbox.group = Some(group);
+        }
+        if let Some(enabled) = env_value("METRICS_ENABLED")? {
+            self.metrics.enabled = enabled;
+        }
+        if let Some(path) = env_value("METRICS_PATH")? {
+            self.metrics.path = path;
+        }
+        if let Some(format) = env_value("LOG_FORMAT")? {
+            self.log.format = format;
+        }
+        if let Some(endpoint) = env_value("OTEL_EXPORTER_OTLP_ENDPOINT")? {
+            self.telemetry.otlp_endpoint = Some(endpoint);
+        }
+        if let Some(name) = env_value("OTEL_SERVICE_NAME")? {
+            self.telemetry.service_name = name;
+        }
+        if let Some(mappings) = env_value::<String>("ASSET_MIME_TYPES")? {
+            self.add_mime_types(&split_list(&mappings))
+                .context("Invalid ASSET_MIME_TYPES")?;
+        }
+
+        Ok(())
+    }
+
+    fn apply_cli(&mut self, cli: &Cli) -> Result<()> {
+        if let Some(listen) = &cli.listen {
+            self.server.listen = listen.clone();
+        }
+        if let Some(port) = cli.port {
+            self.server.port = port;
+        }
+        if let Some(ipv6_only) = cli.ipv6_only {
+            self.server.ipv6_only = ipv6_only;
+        }
+        if let Some(mode) = &cli.unix_socket_mode {
+            self.server.unix_socket_mode = mode.clone();
+        }
+        if let Some(cert) = &cli.tls_cert {
+            self.tls.cert = Some(cert.clone());
+        }
+        if let Some(key) = &cli.tls_key {
+            self.tls.key = Some(key.clone());
+        }
+        if let Some(secs) = cli.shutdown_timeout {
+            self.server.shutdown_timeout_secs = secs;
+        }
+        if let Some(prefix) = &cli.api_prefix {
+            self.server.api_prefix = prefix.clone();
+        }
+        if let Some(cache_key) = &cli.cache_key {
+            self.proxy.cache_key = cache_key.clone();
+        }
+        if let Some(methods) = &cli.exclude_methods {
+            self.proxy.exclude_methods = methods.clone();
+        }
+        if let Some(url) = &cli.frontend_url {
+            self.frontend.url = Some(url.clone());
+        }
+        if let Some(port) = cli.frontend_port {
+            self.frontend.port = Some(port);
+        }
+        if let Some(range) = cli.frontend_port_range {
+            self.frontend.port_range = Some(range);
+        }
+        if let Some(transport) = cli.frontend_transport {
+            self.frontend.transport = transport;
+        }
+        if let Some(port) = cli.dev_port {
+            self.frontend.dev_port = port;
+        }
+        if let Some(secs) = cli.ready_timeout {
+            self.frontend.ready_timeout_secs = secs;
+        }
+        if let Some(path) = &cli.ready_path {
+            self.frontend.ready_path = path.clone();
+        }
+        if let Some(target) = &cli.frontend_log_target {
+            self.frontend.log_target = Some(target.clone());
+        }
+        if let Some(dir) = &cli.extract_dir {
+            self.frontend.extract_dir = Some(dir.clone());
+        }
+        if let Some(names) = &cli.frontend_env_allowlist {
+            self.frontend.sandbox.env = Some(names.clone());
+        }
+        if let Some(mb) = cli.frontend_memory_limit_mb {
+            self.frontend.sandbox.memory_limit_mb = Some(mb);
+        }
+        if let Some(count) = cli.frontend_max_open_files {
+            self.frontend.sandbox.max_open_files = Some(count);
+        }
+        if let Some(secs) = cli.frontend_cpu_limit_secs {
+            self.frontend.sandbox.cpu_limit_secs = Some(secs);
+        }
+        if let Some(enabled) = cli.frontend_process_group {
+            self.frontend.sandbox.process_group = enabled;
+        }
+        if let Some(user) = &cli.frontend_user {
+            self.frontend.sandbox.user = Some(user.clone());
+        }
+        if let Some(group) = &cli.frontend_group {
+            self.frontend.sandbox.group = Some(group.clone());
+        }
+        if let Some(enabled) = cli.metrics {
+            self.metrics.enabled = enabled;
+        }
+        if let Some(path) = &cli.metrics_path {
+            self.metrics.path = path.clone();
+        }
+        if let Some(format) = cli.log_format {
+            self.log.format = format;
+        }
+        if let Some(endpoint) = &cli.otlp_endpoint {
+            self.telemetry.otlp_endpoint = Some(endpoint.clone());
+        }
+        self.add_mime_types(&cli.mime_types)
+            .context("Invalid --mime-type")?;
+
+        Ok(())
+    }
+
+    fn add_mime_types(&mut self, mappings: &[String]) -> Result<()> {
+        for mapping in mappings {
+            let Some((ext, mime)) = mapping.split_once('=') else {
+                anyhow::bail!("{:?} is not an EXT=TYPE mapping", mapping);
+            };
+            self.assets
+                .mime_types
+                .insert(ext.trim().to_string(), mime.trim().to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Brings equivalent spellings into one canonical form before validation
+    fn normalize(&mut self) {
+        let prefix = self.server.api_prefix.trim_end_matches('/');
+        self.server.api_prefix = prefix.to_string();
+
+        if let Some(endpoint) = &mut self.telemetry.otlp_endpoint {
+            *endpoint = endpoint.trim_end_matches('/').to_string();
+        }
+
+        for method in &mut self.proxy.exclude_methods {
+            *method = method.trim().to_ascii_uppercase();
+        }
+
+        self.assets.mime_types = std::mem::take(&mut self.assets.mime_types)
+            .into_iter()
+            .map(|(ext, mime)| (ext.trim_start_matches('.').to_ascii_lowercase(), mime))
+            .collect();
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.server.port == 0 {
+            anyhow::bail!("server.port must not be 0");
+        }
+        self.validate_listen()?;
+
+        if self.tls.cert.is_some() != self.tls.key.is_some() {
+            anyhow::bail!("tls.cert and tls.key must be set together");
+        }
+        if self.tls.reload_interval_secs == 0 {
+            anyhow::bail!("tls.reload_interval_secs must be greater than 0");
+        }
+        if !self.server.api_prefix.starts_with('/') {
+            anyhow::bail!(
+                "server.api_prefix must start with '/' and must not be '/', got {:?}",
+                self.server.api_prefix
+            );
+        }
+
+        let placeholders = template_placeholders(&self.proxy.cache_key)
+            .context("proxy.cache_key is not a valid template")?;
+        if let Some(unknown) = placeholders
+            .iter()
+            .find(|p| !CACHE_KEY_PLACEHOLDERS.contains(p))
+        {
+            anyhow::bail!(
+                "proxy.cache_key uses unknown placeholder {{{}}}, expected one of {{method}}, {{path}}, {{query}}",
+                unknown
+            );
+        }
+        if !placeholders.contains(&"path") {
+            anyhow::bail!("proxy.cache_key must contain {{path}}");
+        }
+        if let Some(method) = self
+            .proxy
+            .exclude_methods
+            .iter()
+            .find(|m| !HTTP_METHODS.contains(&m.as_str()))
+        {
+            anyhow::bail!("proxy.exclude_methods contains unknown HTTP method {:?}", method);
+        }
+
+        if let Some(url) = &self.frontend.url
+            && let Err(e) = url.parse::<Upstream>()
+        {
+            anyhow::bail!("frontend.url {}", e);
+        }
+        if self.frontend.port == Some(0) {
+            anyhow::bail!("frontend.port must not be 0; leave it unset to pick a free port");
+        }
+        if let Some(range) = self.frontend.port_range {
+            if range.start == 0 || range.start > range.end {
+                anyhow::bail!(
+                    "frontend.port_range must be START-END with 0 < START <= END, got {}-{}",
+                    range.start,
+                    range.end
+                );
+            }
+            if self.frontend.port.is_some() {
+                anyhow::bail!("frontend.port and frontend.port_range are mutually exclusive");
+            }
+        }
+        if self.frontend.transport == FrontendTransport::Unix {
+            if !cfg!(unix) {
+                anyhow::bail!("frontend.transport = \"unix\" is only supported on Unix");
+            }
+            if self.frontend.port.is_some() || self.frontend.port_range.is_some() {
+                anyhow::bail!(
+                    "frontend.port and frontend.port_range do not apply to frontend.transport = \"unix\""
+                );
+            }
+        }
+        if self.frontend.dev_port == 0 {
+            anyhow::bail!("frontend.dev_port must not be 0");
+        }
+        if self.frontend.ready_timeout_secs == 0 {
+            anyhow::bail!("frontend.ready_timeout_secs must be greater than 0");
+        }
+
+        if !self.frontend.ready_path.starts_with('/')
+            || axum::http::uri::PathAndQuery::from_str(&self.frontend.ready_path).is_err()
+        {
+            anyhow::bail!(
+                "frontend.ready_path must be a path starting with '/', got {:?}",
+                self.frontend.ready_path
+            );
+        }
+        if let Some(target) = &self.frontend.log_target
+            && (target.is_empty()
+                || !target
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "_-:.".contains(c)))
+        {
+            anyhow::bail!(
+                "frontend.log_target may only contain letters, digits, '_', '-', ':' and '.', got {:?}",
+                target
+            );
+        }
+
+        let sandbox = &self.frontend.sandbox;
+        if let Some(name) = sandbox
+            .env
+            .iter()
+            .flatten()
+            .find(|name| name.is_empty() || name.contains(['=', '\0']))
+        {
+            anyhow::bail!("frontend.sandbox.env contains an invalid variable name {:?}", name);
+        }
+        for (name, limit) in [
+            ("memory_limit_mb", sandbox.memory_limit_mb),
+            ("max_open_files", sandbox.max_open_files),
+            ("cpu_limit_secs", sandbox.cpu_limit_secs),
+        ] {
+            if limit == Some(0) {
+                anyhow::bail!("frontend.sandbox.{} must be greater than 0", name);
+            }
+            if limit.is_some() && !cfg!(unix) {
+                anyhow::bail!("frontend.sandbox.{} is only supported on Unix", name);
+            }
+        }
+        if !cfg!(unix) && (sandbox.process_group || sandbox.user.is_some()) {
+            anyhow::bail!("frontend.sandbox.process_group and user are only supported on Unix");
+        }
+        if sandbox.group.is_some() && sandbox.user.is_none() {
+            anyhow::bail!("frontend.sandbox.group requires frontend.sandbox.user");
+        }
+
+        if !self.metrics.path.starts_with('/') || self.metrics.path.len() < 2 {
+            anyhow::bail!(
+                "metrics.path must start with '/' and must not be '/', got {:?}",
+                self.metrics.path
+            );
+        }
+
+        if let Some(endpoint) = &self.telemetry.otlp_endpoint {
+            if !cfg!(feature = "otel") {
+                anyhow::bail!("telemetry.otlp_endpoint is set, but this build lacks the `otel` feature");
+            }
+            let uri: axum::http::Uri = endpoint.parse().with_context(|| {
+                format!("telemetry.otlp_endpoint {:?} is not a valid URL", endpoint)
+            })?;
+            if !matches!(uri.scheme_str(), Some("http" | "https")) || uri.host().is_none() {
+                anyhow::bail!(
+                    "telemetry.otlp_endpoint must be an http(s) URL with a host, got {:?}",
+                    endpoint
+                );
+            }
+        }
+
+        for (ext, mime) in &self.assets.mime_types {
+            if ext.is_empty() || ext.contains(['/', '.']) {
+                anyhow::bail!("assets.mime_types has an invalid extension {:?}", ext);
+            }
+            if !mime.contains('/') || axum::http::HeaderValue::from_str(mime).is_err() {
+                anyhow::bail!("assets.mime_types.{} has an invalid MIME type {:?}", ext, mime);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_listen(&self) -> Result<()> {
+        let server = &self.server;
+        if server.listen.is_empty() {
+            anyhow::bail!("server.listen must contain at least one address");
+        }
+        if parse_mode(&server.unix_socket_mode).is_none() {
+            anyhow::bail!(
+                "server.unix_socket_mode must be octal permission bits like \"660\", got {:?}",
+                server.unix_socket_mode
+            );
+        }
+
+        let tcp: Vec<_> = server
+            .listen
+            .iter()
+            .filter_map(|addr| addr.socket_addr(server.port))
+            .collect();
+        for (i, addr) in tcp.iter().enumerate() {
+            if addr.port() != 0 && tcp[..i].contains(addr) {
+                anyhow::bail!("server.listen contains {} more than once", addr);
+            }
+            // A dual-stack `::` socket already owns the IPv4 wildcard on the same port
+            let v6_wildcard = SocketAddr::new(IpAddr::V6(Ipv6Addr::UNSPECIFIED), addr.port());
+            if !server.ipv6_only
+                && addr.ip() == IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+                && addr.port() != 0
+                && tcp.contains(&v6_wildcard)
+            {
+                anyhow::bail!(
+                    "server.listen has both {} and {}, but {} is dual-stack; drop {} or set server.ipv6_only",
+                    addr, v6_wildcard, v6_wildcard, addr
+                );
+            }
+        }
+
+        let mut unix_paths: Vec<_> = server
+            .listen
+            .iter()
+            .filter_map(|addr| match addr {
+                ListenAddr::Unix(path) => Some(path),
+                ListenAddr::Tcp { .. } => None,
+            })
+            .collect();
+        let count = unix_paths.len();
+        unix_paths.sort();
+        unix_paths.dedup();
+        if unix_paths.len() != count {
+            anyhow::bail!("server.listen contains the same Unix socket more than once");
+        }
+
+        Ok(())
+    }
+
+    /// The effective configuration as TOML, for `--print-config`
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string_pretty(self).context("Failed to serialize configuration")
+    }
+}
+
+/// Reads and parses an environment variable, treating unset and empty as absent
+fn env_value<T>(name: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match std::env::var(name) {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|e| anyhow::anyhow!("Invalid {}={:?}: {}", name, value, e)),
+        _ => Ok(None),
+    }
+}
+
+/// Parses octal permission bits such as `660` or `0o660`
+fn parse_mode(mode: &str) -> Option<u32> {
+    let digits = mode.trim_start_matches("0o");
+    u32::from_str_radix(digits, 8).ok().filter(|mode| *mode <= 0o777)
+}
+
+fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Lists the `{name}` placeholders in a template, failing on unbalanced braces
+fn template_placeholders(template: &str) -> Result<Vec<&str>> {
+    let mut placeholders = Vec::new();
+    let mut rest = template;
+
+    while let Some(open) = rest.find(['{', '}']) {
+        if rest[open..].starts_with('}') {
+            anyhow::bail!("unmatched '}}' in {:?}", template);
+        }
+        let Some(close) = rest[open..].find('}') else {
+            anyhow::bail!("unmatched '{{' in {:?}", template);
+        };
+        placeholders.push(&rest[open + 1..open + close]);
+        rest = &rest[open + close + 1..];
+    }
+
+    Ok(placeholders)
+}
+
+/// Substitutes `{name}` placeholders in a template that passed validation
+fn render_template<'a>(template: &str, value: impl Fn(&str) -> &'a str) -> String {
+    let mut rendered = String::with_capacity(template.len() + 32);
+    let mut rest = template;
+
+    while let Some(open) = rest.find('{') {
+        let Some(close) = rest[open..].find('}') else {
+            break;
+        };
+        rendered.push_str(&rest[..open]);
+        rendered.push_str(value(&rest[open + 1..open + close]));
+        rest = &rest[open + close + 1..];
+    }
+    rendered.push_str(rest);
+
+    rendered
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses a config file body and runs the same normalization and checks as `Config::load`
+    fn parse(toml: &str) -> Result<Config> {
+        let mut config: Config = toml::from_str(toml)?;
+        config.normalize();
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn error(toml: &str) -> String {
+        format!("{:#}", parse(toml).unwrap_err())
+    }
+
+    #[test]
+    fn later_sources_win() {
+        let path = std::env::temp_dir().join(format!("config-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            "[server]\nshutdown_timeout_secs = 1\napi_prefix = \"/file\"\n\
+             [proxy]\ncache_key = \"file:{path}\"\n",
+        )
+        .unwrap();
+        let cli = Cli::try_parse_from([
+            "server",
+            "--config",
+            path.to_str().unwrap(),
+            "--cache-key",
+            "cli:{path}",
+        ])
+        .unwrap();
+
+        // No other test reads these variables
+        unsafe {
+            std::env::set_var("API_PREFIX", "/env");
+            std::env::set_var("CACHE_KEY", "env:{path}");
+        }
+        let config = Config::load(&cli);
+        unsafe {
+            std::env::remove_var("API_PREFIX");
+            std::env::remove_var("CACHE_KEY");
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        let config = config.unwrap();
+        assert_eq!(config.server.shutdown_timeout_secs, 1);
+        assert_eq!(config.server.api_prefix, "/env");
+        assert_eq!(config.proxy.cache_key, "cli:{path}");
+    }
+
+    #[test]
+    fn rejects_unknown_fields() {
+        assert!(error("[server]\nlisten_port = 8080\n").contains("unknown field `listen_port`"));
+        assert!(error("[cache]\nenabled = true\n").contains("unknown field `cache`"));
+        assert!(error("[frontend.sandbox]\nmemory = 10\n").contains("unknown field `memory`"));
+    }
+
+    #[test]
+    fn normalizes_the_api_prefix() {
+        let config = parse("[server]\napi_prefix = \"/v1/\"\n").unwrap();
+        assert_eq!(config.server.api_prefix, "/v1");
+
+        // `/` would shadow every route, and trims down to an empty prefix
+        for prefix in ["/", "", "v1"] {
+            let message = error(&format!("[server]\napi_prefix = {:?}\n", prefix));
+            assert!(
+                message.contains("server.api_prefix must start with '/'"),
+                "{}",
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn checks_the_cache_key_template() {
+        let config = parse("[proxy]\ncache_key = \"{method}:{path}?{query}\"\n").unwrap();
+        assert_eq!(
+            config.proxy.render_cache_key("GET", "/blog", "page=2"),
+            "GET:/blog?page=2"
+        );
+
+        for (template, expected) in [
+            ("{host}{path}", "unknown placeholder {host}"),
+            ("{method}:{query}", "must contain {path}"),
+            ("{path", "unmatched '{'"),
+            ("path}", "unmatched '}'"),
+        ] {
+            let message = error(&format!("[proxy]\ncache_key = {:?}\n", template));
+            assert!(message.contains(expected), "{}: {}", template, message);
+        }
+    }
+
+    #[test]
+    fn checks_the_frontend_port_range() {
+        let config = parse("[frontend]\nport_range = \"40000-40100\"\n").unwrap();
+        assert_eq!(
+            config.frontend.port_range,
+            Some(PortRange {
+                start: 40000,
+                end: 40100
+            })
+        );
+
+        for range in ["40100-40000", "0-10"] {
+            let message = error(&format!("[frontend]\nport_range = {:?}\n", range));
+            assert!(message.contains("0 < START <= END"), "{}", message);
+        }
+        assert!(error("[frontend]\nport_range = \"40000\"\n").contains("expected START-END"));
+        assert!(
+            error("[frontend]\nport = 4000\nport_range = \"40000-40100\"\n")
+                .contains("mutually exclusive")
+        );
+    }
+
+    #[test]
+    fn rejects_duplicate_listen_addresses() {
+        let message = error("[server]\nlisten = [\"127.0.0.1:8080\", \"127.0.0.1:8080\"]\n");
+        assert!(
+            message.contains("127.0.0.1:8080 more than once"),
+            "{}",
+            message
+        );
+
+        // Addresses without a port use server.port
+        let message =
+            error("[server]\nport = 8080\nlisten = [\"127.0.0.1\", \"127.0.0.1:8080\"]\n");
+        assert!(message.contains("more than once"), "{}", message);
+
+        let message = error("[server]\nlisten = [\"unix:/tmp/a.sock\", \"unix:/tmp/a.sock\"]\n");
+        assert!(message.contains("same Unix socket"), "{}", message);
+
+        parse(
+            "[server]\nlisten = [\"127.0.0.1:8080\", \"127.0.0.1:8081\", \"unix:/tmp/a.sock\"]\n",
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn rejects_ipv4_wildcards_shadowed_by_dual_stack() {
+        let listen = "listen = [\"0.0.0.0:8080\", \"[::]:8080\"]\n";
+        let message = error(&format!("[server]\n{}", listen));
+        assert!(message.contains("is dual-stack"), "{}", message);
+
+        parse(&format!("[server]\nipv6_only = true\n{}", listen)).unwrap();
+        parse("[server]\nlisten = [\"0.0.0.0:8080\", \"[::]:8081\"]\n").unwrap();
+    }
+
+    #[test]
+    fn parses_the_unix_socket_mode() {
+        for (mode, bits) in [("660", 0o660), ("0o600", 0o600), ("777", 0o777)] {
+            let config = parse(&format!("[server]\nunix_socket_mode = {:?}\n", mode)).unwrap();
+            assert_eq!(
+                config.server.listen_options().unix_socket_mode,
+                bits,
+                "{}",
+                mode
+            );
+        }
+
+        for mode in ["", "rw-rw----", "680", "1777"] {
+            let message = error(&format!("[server]\nunix_socket_mode = {:?}\n", mode));
+            assert!(message.contains("server.unix_socket_mode"), "{}", message);
+        }
+    }
+}
@@ -0,0 +1,415 @@
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File, OpenOptions};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::{debug, info, warn};
+
+use super::sandbox::Credentials;
+
+/// Hex digits of the SHA-256 kept in file names
+const HASH_LEN: usize = 16;
+
+/// Files this process extracted or reuses. They stay open and locked for the
+/// life of the process, because the supervisor may restart the frontend from
+/// them at any time and other instances must not clean them up meanwhile.
+static IN_USE: Mutex<Vec<File>> = Mutex::new(Vec::new());
+
+/// A file embedded in the server that has to be on disk to be run
+pub struct Artifact {
+    /// Start of the file name, e.g. `client`
+    pub name: &'static str,
+    /// End of the file name after the hash, e.g. `.js`
+    pub extension: &'static str,
+    pub bytes: &'static [u8],
+    pub executable: bool,
+}
+
+impl Artifact {
+    /// Writes the artifact to the per-user directory under `base` (the system temp
+    /// dir by default) and returns its path. With an `owner`, the directory and
+    /// file belong to the user the frontend runs as instead.
+    ///
+    /// The file name carries a hash of the content, so other builds and other
+    /// instances never write to the same path and a running executable is never
+    /// overwritten. Files are written under a temporary name and renamed into
+    /// place; one whose content still matches the hash is reused as is.
+    pub fn extract(&self, base: Option<&Path>, owner: Option<Credentials>) -> Result<PathBuf> {
+        let dir = extraction_dir(base, owner)?;
+        #[cfg(target_os = "linux")]
+        if self.executable && is_noexec(&dir) {
+            anyhow::bail!(
+                "{:?} is on a filesystem mounted noexec; set frontend.extract_dir (FRONTEND_EXTRACT_DIR) to a directory that allows executables",
+                dir
+            );
+        }
+        let hash = format!("{:x}", Sha256::digest(self.bytes));
+        let file_name = format!("{}-{}{}", self.name, &hash[..HASH_LEN], self.extension);
+        let path = dir.join(&file_name);
+
+        let file = match reuse(&path, &hash)? {
+            Some(file) => {
+                info!("Reusing extracted {} at {:?}", self.name, path);
+                file
+            }
+            None => {
+                info!("Extracting {} to {:?}", self.name, path);
+                self.write(&path, owner)?
+            }
+        };
+        IN_USE.lock().expect("extracted files lock poisoned").push(file);
+
+        self.remove_stale(&dir, &file_name);
+        Ok(path)
+    }
+
+    /// Writes to a fresh temporary file next to `path` and renames it into place
+    fn write(&self, path: &Path, owner: Option<Credentials>) -> Result<File> {
+        let temp = path.with_file_name(format!(
+            "{}.{}.tmp",
+            path.file_name().unwrap_or_default().to_string_lossy(),
+            uuid::Uuid::new_v4().simple()
+        ));
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(if self.executable { 0o700 } else { 0o600 });
+        }
+        let mut file = options
+            .open(&temp)
+            .with_context(|| format!("Failed to create {:?}", temp))?;
+        // Locked before the rename, so the file is never unlocked under its final name
+        lock::shared(&file)?;
+        #[cfg(unix)]
+        if let Some(owner) = owner {
+            std::os::unix::fs::fchown(&file, Some(owner.uid), Some(owner.gid))
+                .with_context(|| format!("Failed to hand {:?} to the frontend's user", temp))?;
+        }
+
+        let written = std::io::Write::write_all(&mut file, self.bytes)
+            .and_then(|()| file.sync_all())
+            .and_then(|()| fs::rename(&temp, path));
+        if let Err(e) = written {
+            fs::remove_file(&temp).ok();
+            return Err(e).with_context(|| format!("Failed to write {:?}", path));
+        }
+
+        // Executables cannot be started while a writable descriptor is open (ETXTBSY),
+        // so only a read-only one is kept, locked before the writable one is closed
+        let readonly = File::open(path).with_context(|| format!("Failed to open {:?}", path))?;
+        lock::shared(&readonly)?;
+        Ok(readonly)
+    }
+
+    /// Removes other versions of this artifact that no running instance holds
+    fn remove_stale(&self, dir: &Path, current: &str) {
+        let prefix = format!("{}-", self.name);
+        let Ok(entries) = fs::read_dir(dir) else {
+            return;
+        };
+
+        for entry in entries.flatten() {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if !name.starts_with(&prefix) || name == current {
+                continue;
+            }
+            let path = entry.path();
+            if !fs::symlink_metadata(&path).is_ok_and(|meta| meta.is_file()) {
+                continue;
+            }
+
+            match lock::try_exclusive(&path) {
+                Ok(true) => match fs::remove_file(&path) {
+                    Ok(()) => debug!("Removed stale {:?}", path),
+                    Err(e) => warn!("Failed to remove stale {:?}: {}", path, e),
+                },
+                Ok(false) => debug!("Keeping {:?}, another instance is using it", path),
+                Err(e) => debug!("Keeping {:?}: {}", path, e),
+            }
+        }
+    }
+}
+
+/// Opens and locks the file at `path` if its content hashes to `hash`
+fn reuse(path: &Path, hash: &str) -> Result<Option<File>> {
+    let mut file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e).with_context(|| format!("Failed to open {:?}", path)),
+    };
+    lock::shared(&file)?;
+
+    // Another instance may have removed the file while we waited for the lock
+    if !lock::is_linked_at(&file, path) {
+        return Ok(None);
+    }
+
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).with_context(|| format!("Failed to read {:?}", path))?;
+    if format!("{:x}", hasher.finalize()) != hash {
+        warn!("{:?} does not match its hash, extracting it again", path);
+        return Ok(None);
+    }
+
+    Ok(Some(file))
+}
+
+/// `{base}/{WORKSPACE_NAME}-{uid}`, private to the user the frontend runs as
+#[cfg(unix)]
+fn extraction_dir(base: Option<&Path>, owner: Option<Credentials>) -> Result<PathBuf> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+
+    // SAFETY: geteuid has no preconditions and cannot fail
+    let euid = unsafe { libc::geteuid() };
+    let uid = owner.map_or(euid, |owner| owner.uid);
+    let base = base.map_or_else(std::env::temp_dir, Path::to_path_buf);
+    let dir = base.join(format!("{}-{}", env!("WORKSPACE_NAME"), uid));
+
+    fs::DirBuilder::new()
+        .mode(0o700)
+        .recursive(true)
+        .create(&dir)
+        .with_context(|| format!("Failed to create {:?}", dir))?;
+
+    // Not followed if it is a symlink, so a planted link is rejected rather than used
+    let meta = fs::symlink_metadata(&dir).with_context(|| format!("Failed to inspect {:?}", dir))?;
+    if !meta.is_dir() {
+        anyhow::bail!("Extraction directory {:?} is a symlink or not a directory", dir);
+    }
+    match owner {
+        _ if meta.uid() == uid => {}
+        // Only reached as root, for a directory this server created for the frontend's user
+        Some(owner) if meta.uid() == euid => {
+            std::os::unix::fs::lchown(&dir, Some(owner.uid), Some(owner.gid))
+                .with_context(|| format!("Failed to hand {:?} to the frontend's user", dir))?;
+        }
+        _ => anyhow::bail!("Extraction directory {:?} belongs to another user", dir),
+    }
+    if meta.mode() & 0o077 != 0 {
+        warn!("Extraction directory {:?} was accessible to other users, restricting it", dir);
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o700))
+            .with_context(|| format!("Failed to restrict {:?}", dir))?;
+    }
+
+    Ok(dir)
+}
+
+/// The temporary directory is already per user on Windows
+#[cfg(not(unix))]
+fn extraction_dir(base: Option<&Path>, _owner: Option<Credentials>) -> Result<PathBuf> {
+    let base = base.map_or_else(std::env::temp_dir, Path::to_path_buf);
+    let dir = base.join(env!("WORKSPACE_NAME"));
+    fs::create_dir_all(&dir).with_context(|| format!("Failed to create {:?}", dir))?;
+    Ok(dir)
+}
+
+/// Whether files in `dir` cannot be executed because of how its filesystem is mounted
+#[cfg(target_os = "linux")]
+fn is_noexec(dir: &Path) -> bool {
+    use std::os::unix::ffi::OsStrExt;
+
+    let Ok(path) = std::ffi::CString::new(dir.as_os_str().as_bytes()) else {
+        return false;
+    };
+    let mut stats = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is a valid C string and `stats` points to enough space for the result
+    if unsafe { libc::statvfs(path.as_ptr(), stats.as_mut_ptr()) } != 0 {
+        return false;
+    }
+    // SAFETY: statvfs succeeded, so it filled in `stats`
+    let stats = unsafe { stats.assume_init() };
+    stats.f_flag & libc::ST_NOEXEC != 0
+}
+
+/// Advisory locks marking extracted files as in use
+#[cfg(unix)]
+mod lock {
+    use anyhow::{Context, Result};
+    use std::fs::File;
+    use std::os::fd::AsRawFd;
+    use std::os::unix::fs::MetadataExt;
+    use std::path::Path;
+
+    pub fn shared(file: &File) -> Result<()> {
+        // SAFETY: flock only takes the descriptor of a file we hold open
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_SH) } != 0 {
+            return Err(std::io::Error::last_os_error()).context("Failed to lock extracted file");
+        }
+        Ok(())
+    }
+
+    /// Whether nobody holds the file; the lock is released again when this returns
+    pub fn try_exclusive(path: &Path) -> std::io::Result<bool> {
+        let file = File::open(path)?;
+        // SAFETY: as above
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+            return Ok(true);
+        }
+        match std::io::Error::last_os_error() {
+            e if e.kind() == std::io::ErrorKind::WouldBlock => Ok(false),
+            e => Err(e),
+        }
+    }
+
+    /// Whether `path` still names the open file
+    pub fn is_linked_at(file: &File, path: &Path) -> bool {
+        match (file.metadata(), std::fs::metadata(path)) {
+            (Ok(open), Ok(linked)) => open.dev() == linked.dev() && open.ino() == linked.ino(),
+            _ => false,
+        }
+    }
+}
+
+/// Without advisory locks there is no telling whether another instance still
+/// uses a file, so stale versions are left in place.
+#[cfg(not(unix))]
+mod lock {
+    use anyhow::Result;
+    use std::fs::File;
+    use std::path::Path;
+
+    pub fn shared(_file: &File) -> Result<()> {
+        Ok(())
+    }
+
+    pub fn try_exclusive(_path: &Path) -> std::io::Result<bool> {
+        Ok(false)
+    }
+
+    pub fn is_linked_at(_file: &File, path: &Path) -> bool {
+        path.exists()
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+
+    const ARTIFACT: Artifact = Artifact {
+        name: "client",
+        extension: ".js",
+        bytes: b"console.log('frontend')",
+        executable: false,
+    };
+
+    /// A fresh base directory and the per-user directory extraction uses inside it
+    fn base() -> (PathBuf, PathBuf) {
+        let base = std::env::temp_dir().join(format!("extract-{}", uuid::Uuid::new_v4()));
+        fs::create_dir(&base).unwrap();
+        // SAFETY: geteuid has no preconditions and cannot fail
+        let uid = unsafe { libc::geteuid() };
+        let dir = base.join(format!("{}-{}", env!("WORKSPACE_NAME"), uid));
+        (base, dir)
+    }
+
+    #[test]
+    fn extracts_into_a_private_directory() {
+        let (base, dir) = base();
+        let path = ARTIFACT.extract(Some(&base), None).unwrap();
+
+        assert_eq!(path.parent(), Some(dir.as_path()));
+        assert_eq!(fs::read(&path).unwrap(), ARTIFACT.bytes);
+        assert_eq!(fs::metadata(&dir).unwrap().mode() & 0o777, 0o700);
+        assert_eq!(fs::metadata(&path).unwrap().mode() & 0o777, 0o600);
+        // No temporary files are left behind
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+        fs::remove_dir_all(&base).ok();
+    }
+
+    #[test]
+    fn reuses_a_matching_file() {
+        let (base, _) = base();
+        let first = ARTIFACT.extract(Some(&base), None).unwrap();
+        let inode = fs::metadata(&first).unwrap().ino();
+
+        let second = ARTIFACT.extract(Some(&base), None).unwrap();
+        assert_eq!(second, first);
+        assert_eq!(
+            fs::metadata(&second).unwrap().ino(),
+            inode,
+            "not written again"
+        );
+        fs::remove_dir_all(&base).ok();
+    }
+
+    #[test]
+    fn replaces_a_file_that_does_not_match_its_hash() {
+        let (base, _) = base();
+        let path = ARTIFACT.extract(Some(&base), None).unwrap();
+        fs::write(&path, "tampered").unwrap();
+
+        assert_eq!(ARTIFACT.extract(Some(&base), None).unwrap(), path);
+        assert_eq!(fs::read(&path).unwrap(), ARTIFACT.bytes);
+        fs::remove_dir_all(&base).ok();
+    }
+
+    #[test]
+    fn rejects_a_planted_symlink() {
+        let (base, dir) = base();
+        let target = base.join("elsewhere");
+        fs::create_dir(&target).unwrap();
+        std::os::unix::fs::symlink(&target, &dir).unwrap();
+
+        let error = ARTIFACT.extract(Some(&base), None).unwrap_err().to_string();
+        assert!(error.contains("is a symlink"), "{}", error);
+        assert_eq!(
+            fs::read_dir(&target).unwrap().count(),
+            0,
+            "nothing written through the link"
+        );
+        fs::remove_dir_all(&base).ok();
+    }
+
+    #[test]
+    fn restricts_a_directory_open_to_others() {
+        let (base, dir) = base();
+        fs::create_dir(&dir).unwrap();
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o777)).unwrap();
+
+        ARTIFACT.extract(Some(&base), None).unwrap();
+        assert_eq!(fs::metadata(&dir).unwrap().mode() & 0o777, 0o700);
+        fs::remove_dir_all(&base).ok();
+    }
+
+    #[test]
+    fn rejects_a_directory_of_another_user() {
+        let (base, dir) = base();
+        fs::create_dir(&dir).unwrap();
+        // Handing the directory to another user needs root; others cannot set this up
+        if std::os::unix::fs::chown(&dir, Some(65534), Some(65534)).is_err() {
+            eprintln!("skipped: chown needs root");
+            fs::remove_dir_all(&base).ok();
+            return;
+        }
+
+        let error = ARTIFACT.extract(Some(&base), None).unwrap_err().to_string();
+        assert!(error.contains("belongs to another user"), "{}", error);
+        fs::remove_dir_all(&base).ok();
+    }
+
+    #[test]
+    fn removes_stale_versions_nobody_holds() {
+        let (base, dir) = base();
+        fs::create_dir_all(&dir).unwrap();
+        let stale = dir.join("client-0000000000000000.js");
+        let held = dir.join("client-1111111111111111.js");
+        let unrelated = dir.join("server-0000000000000000.js");
+        for path in [&stale, &held, &unrelated] {
+            fs::write(path, "old").unwrap();
+        }
+        // Another instance still running from this version keeps it locked
+        let holder = File::open(&held).unwrap();
+        lock::shared(&holder).unwrap();
+
+        ARTIFACT.extract(Some(&base), None).unwrap();
+        assert!(!stale.exists());
+        assert!(held.exists());
+        assert!(unrelated.exists());
+        fs::remove_dir_all(&base).ok();
+    }
+}
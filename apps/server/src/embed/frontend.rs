@@ -1,106 +1,165 @@
-use std::io::{BufRead, BufReader, Write};
-use std::net::TcpStream;
-use std::process::{Command, Stdio};
-use std::sync::{Arc, atomic::{AtomicBool, Ordering}};
-use std::thread;
-use std::time::{Duration, Instant};
-use tracing;
-use anyhow::{Context, Result};
+use anyhow::Result;
+use std::path::Path;
+#[cfg(target_os = "linux")]
+use std::path::PathBuf;
+#[cfg(target_os = "linux")]
+use tracing::{info, warn};
+
+use super::extract::Artifact;
+use super::launcher::{FrontendLauncher, LaunchMode};
+use super::sandbox::Credentials;
 
 #[cfg(target_os = "windows")]
-const APP_BINARY: &[u8] = include_bytes!("../../../client/dist/client.exe");
+const APP_BINARY: Artifact = Artifact {
+    name: "client",
+    extension: ".exe",
+    bytes: include_bytes!("../../../client/dist/client.exe"),
+    executable: true,
+};
 
 #[cfg(not(target_os = "windows"))]
-const APP_BINARY: &[u8] = include_bytes!("../../../client/dist/client");
-
-pub fn run_frontend_binary(frontend_port: u16) -> Result<()> {
-    // Create temp directory for executable with project name
-    let project_name = env!("WORKSPACE_NAME");
-    let temp_dir = std::env::temp_dir().join(project_name);
-    std::fs::create_dir_all(&temp_dir)?;
-    
-    #[cfg(target_os = "windows")]
-    let exe_path = temp_dir.join("client.exe");
-    
-    #[cfg(not(target_os = "windows"))]
-    let exe_path = temp_dir.join("client");
-    
-    tracing::info!("Extracting frontend binary to {:?}", exe_path);
-    
-    // Write embedded binary to temp location
-    let mut file = std::fs::File::create(&exe_path)?;
-    file.write_all(APP_BINARY)?;
-    drop(file);
-    
-    // Set executable permissions on Unix
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::PermissionsExt;
-        let mut perms = std::fs::metadata(&exe_path)?.permissions();
-        perms.set_mode(0o755);
-        std::fs::set_permissions(&exe_path, perms)?;
+const APP_BINARY: Artifact = Artifact {
+    name: "client",
+    extension: "",
+    bytes: include_bytes!("../../../client/dist/client"),
+    executable: true,
+};
+
+/// Production launcher for the `bun_compile` build.
+///
+/// On Linux the compiled client runs from memory, so it works with a `noexec`
+/// temp dir; elsewhere, or when that is not possible, it is extracted under
+/// `extract_dir` and run from there, owned by `owner` if it runs as another user.
+pub fn frontend_launcher(
+    extract_dir: Option<&Path>,
+    owner: Option<Credentials>,
+) -> Result<FrontendLauncher> {
+    #[cfg(target_os = "linux")]
+    let mode = binary_mode(&APP_BINARY, super::memfd::load, extract_dir, owner)?;
+    #[cfg(not(target_os = "linux"))]
+    let mode = extracted_mode(&APP_BINARY, extract_dir, owner)?;
+
+    Ok(FrontendLauncher::new(mode))
+}
+
+/// Runs `artifact` from memory through `load`, falling back to extracting it
+#[cfg(target_os = "linux")]
+fn binary_mode(
+    artifact: &Artifact,
+    load: fn(&str, &[u8]) -> Result<PathBuf>,
+    extract_dir: Option<&Path>,
+    owner: Option<Credentials>,
+) -> Result<LaunchMode> {
+    let memory_error = match load(artifact.name, artifact.bytes) {
+        Ok(executable) => {
+            info!("Running frontend binary from memory at {:?}", executable);
+            return Ok(LaunchMode::Binary {
+                executable,
+                working_dir: None,
+            });
+        }
+        Err(e) => {
+            warn!("Cannot run frontend binary from memory, extracting it instead: {:#}", e);
+            e
+        }
+    };
+
+    extracted_mode(artifact, extract_dir, owner).map_err(|e| {
+        anyhow::anyhow!(
+            "Failed to prepare frontend binary: running it from memory failed ({:#}), then extracting it failed ({:#})",
+            memory_error,
+            e
+        )
+    })
+}
+
+/// Runs `artifact` from its extracted copy, inside the directory it was extracted to
+fn extracted_mode(
+    artifact: &Artifact,
+    extract_dir: Option<&Path>,
+    owner: Option<Credentials>,
+) -> Result<LaunchMode> {
+    let executable = artifact.extract(extract_dir, owner)?;
+
+    Ok(LaunchMode::Binary {
+        working_dir: executable.parent().map(Path::to_path_buf),
+        executable,
+    })
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    const ARTIFACT: Artifact = Artifact {
+        name: "frontend-test",
+        extension: "",
+        bytes: b"\x7fELF",
+        executable: true,
+    };
+
+    fn unavailable(_: &str, _: &[u8]) -> Result<PathBuf> {
+        anyhow::bail!("memfd_create failed")
     }
-    
-    // Spawn the executable with piped output
-    let mut child = Command::new(&exe_path)
-        .current_dir(&temp_dir)
-        .env("PORT", frontend_port.to_string())
-        .env("HOST", "127.0.0.1")
-        .env("NODE_ENV", "production")
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()
-        .context("Failed to spawn frontend binary")?;
-    
-    tracing::info!("Frontend binary started at {:?}", exe_path);
-    
-    let ready = Arc::new(AtomicBool::new(false));
-    let ready_clone = ready.clone();
-    
-    // Spawn threads to read and log stdout/stderr
-    if let Some(stdout) = child.stdout.take() {
-        std::thread::spawn(move || {
-            let reader = BufReader::new(stdout);
-            for line in reader.lines() {
-                if let Ok(line) = line {
-                    if line.contains("Listening on") {
-                        ready_clone.store(true, Ordering::SeqCst);
-                    }
-                    tracing::info!(target: "frontend", "{}", line);
-                }
-            }
-        });
+
+    fn base() -> PathBuf {
+        let base = std::env::temp_dir().join(format!("frontend-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&base).unwrap();
+        base
     }
-    
-    if let Some(stderr) = child.stderr.take() {
-        std::thread::spawn(move || {
-            let reader = BufReader::new(stderr);
-            for line in reader.lines() {
-                if let Ok(line) = line {
-                    tracing::warn!(target: "frontend", "{}", line);
-                }
-            }
-        });
+
+    #[test]
+    fn runs_from_memory_when_possible() {
+        let base = base();
+        match binary_mode(&ARTIFACT, super::super::memfd::load, Some(&base), None).unwrap() {
+            LaunchMode::Binary {
+                executable,
+                working_dir: None,
+            } => assert!(executable.starts_with("/proc/self/fd")),
+            other => panic!("{:?}", other),
+        }
+        // Nothing was extracted
+        assert_eq!(std::fs::read_dir(&base).unwrap().count(), 0);
+        std::fs::remove_dir_all(&base).ok();
     }
-    
-    tracing::info!("Waiting for frontend to be ready on port {}...", frontend_port);
-    let start = Instant::now();
-    let timeout = Duration::from_secs(30);
-    
-    // Wait for either the "Listening on" log or port to be available
-    while !ready.load(Ordering::SeqCst) && start.elapsed() < timeout {
-        if TcpStream::connect(("127.0.0.1", frontend_port)).is_ok() {
-            tracing::info!("Frontend port {} is now available", frontend_port);
-            break;
+
+    #[test]
+    fn falls_back_to_extracting() {
+        let base = base();
+        match binary_mode(&ARTIFACT, unavailable, Some(&base), None).unwrap() {
+            LaunchMode::Binary {
+                executable,
+                working_dir: Some(working_dir),
+            } => {
+                assert!(executable.starts_with(&base));
+                assert_eq!(executable.parent(), Some(working_dir.as_path()));
+                assert_eq!(std::fs::read(&executable).unwrap(), ARTIFACT.bytes);
+            }
+            other => panic!("{:?}", other),
         }
-        thread::sleep(Duration::from_millis(100));
+        std::fs::remove_dir_all(&base).ok();
     }
-    
-    if start.elapsed() >= timeout {
-        anyhow::bail!("Frontend failed to start within {} seconds", timeout.as_secs());
+
+    #[test]
+    fn reports_both_failures() {
+        let base = base();
+        // A file where the extraction directory's parent should be
+        let file = base.join("not-a-directory");
+        std::fs::write(&file, "").unwrap();
+
+        let error = binary_mode(&ARTIFACT, unavailable, Some(&file), None)
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("running it from memory failed (memfd_create failed)"),
+            "{}",
+            error
+        );
+        assert!(
+            error.contains("then extracting it failed (Failed to create"),
+            "{}",
+            error
+        );
+        std::fs::remove_dir_all(&base).ok();
     }
-    
-    tracing::info!("Frontend is ready after {:?}", start.elapsed());
-    
-    Ok(())
 }
@@ -0,0 +1,683 @@
+use anyhow::{Context, Result};
+use axum::http::Uri;
+use std::fmt;
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use super::output::{forward_output, LogTarget, RecentOutput};
+use super::port::{self, PortAllocation};
+use super::probe;
+use super::process::{bind_to_parent, FrontendProcess};
+use super::sandbox::Sandbox;
+#[cfg(unix)]
+use super::socket::FrontendSocket;
+use crate::upstream::Upstream;
+
+/// How long a single readiness probe may take
+const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Pause between readiness probes
+const POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Ports tried before giving up when other processes keep taking them
+const PORT_ATTEMPTS: usize = 5;
+
+/// How the frontend is provided
+///
+/// Each build only constructs the modes that apply to it.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub enum LaunchMode {
+    /// Self-contained executable produced by `bun build --compile`, run in
+    /// `working_dir` or in the server's own working directory
+    Binary {
+        executable: PathBuf,
+        working_dir: Option<PathBuf>,
+    },
+    /// JavaScript bundle run by the `bun` found on `PATH`
+    Bun { bundle: PathBuf },
+    /// `bun run dev` inside the client directory
+    ViteDev { client_dir: PathBuf },
+    /// A frontend managed outside this process; it is only waited on, never spawned
+    External { url: String },
+}
+
+/// Where a spawned frontend listens
+///
+/// Each build only constructs the variants that apply to it.
+#[allow(dead_code)]
+#[derive(Clone)]
+pub enum FrontendListen {
+    /// A loopback TCP port
+    Tcp(PortAllocation),
+    /// A Unix socket in a private directory
+    #[cfg(unix)]
+    Unix(Arc<FrontendSocket>),
+}
+
+impl fmt::Debug for FrontendListen {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrontendListen::Tcp(ports) => f.debug_tuple("Tcp").field(ports).finish(),
+            #[cfg(unix)]
+            FrontendListen::Unix(socket) => f.debug_tuple("Unix").field(&socket.path()).finish(),
+        }
+    }
+}
+
+/// Where a frontend accepts connections
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FrontendAddress {
+    Tcp { host: String, port: u16 },
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl fmt::Display for FrontendAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FrontendAddress::Tcp { host, port } if host.contains(':') => {
+                write!(f, "[{}]:{}", host, port)
+            }
+            FrontendAddress::Tcp { host, port } => write!(f, "{}:{}", host, port),
+            #[cfg(unix)]
+            FrontendAddress::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// Starts the frontend in one of the [`LaunchMode`]s and waits until it answers HTTP requests.
+///
+/// Every mode shares the same output forwarding, readiness detection and
+/// lifecycle handling, so the launcher can be handed to the supervisor as is.
+/// The first successful launch pins the frontend's address, because the proxy
+/// is created for it; restarts reuse that address.
+#[derive(Debug, Clone)]
+pub struct FrontendLauncher {
+    mode: LaunchMode,
+    listen: FrontendListen,
+    ready_timeout: Duration,
+    ready_path: String,
+    log_target: LogTarget,
+    sandbox: Sandbox,
+    /// Once true, a launch stops waiting for readiness and stops the half-started process
+    shutdown: watch::Receiver<bool>,
+    /// Port of the first successful TCP launch
+    pinned_port: Arc<Mutex<Option<u16>>>,
+}
+
+impl FrontendLauncher {
+    pub fn new(mode: LaunchMode) -> Self {
+        let log_target = match mode {
+            LaunchMode::ViteDev { .. } => "dev-frontend",
+            _ => "frontend",
+        };
+
+        Self {
+            mode,
+            listen: FrontendListen::Tcp(PortAllocation::Ephemeral),
+            ready_timeout: Duration::from_secs(30),
+            ready_path: "/".to_string(),
+            log_target: LogTarget::new(log_target),
+            sandbox: Sandbox::default(),
+            shutdown: watch::channel(false).1,
+            pinned_port: Arc::default(),
+        }
+    }
+
+    /// Set where a spawned frontend listens; ignored for external frontends
+    pub fn with_listen(mut self, listen: FrontendListen) -> Self {
+        self.listen = listen;
+        self
+    }
+
+    /// Set how long to wait for the frontend to become ready
+    pub fn with_ready_timeout(mut self, timeout: Duration) -> Self {
+        self.ready_timeout = timeout;
+        self
+    }
+
+    /// Set the path that must answer 2xx before the frontend counts as ready
+    pub fn with_ready_path(mut self, path: &str) -> Self {
+        self.ready_path = path.to_string();
+        self
+    }
+
+    /// Restrict the spawned process; ignored for external frontends
+    pub fn with_sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    /// Abandon launches once `shutdown` flips to true instead of waiting out the ready timeout
+    pub fn with_shutdown(mut self, shutdown: watch::Receiver<bool>) -> Self {
+        self.shutdown = shutdown;
+        self
+    }
+
+    /// Log the child's output under this tracing target instead of the mode's default
+    pub fn with_log_target(mut self, target: &str) -> Self {
+        self.log_target = LogTarget::new(target);
+        self
+    }
+
+    /// Where the proxy should forward requests to; known once the first launch succeeded
+    pub fn upstream(&self) -> Result<Upstream> {
+        if let LaunchMode::External { url } = &self.mode {
+            return url.parse().with_context(|| format!("Invalid frontend URL {:?}", url));
+        }
+
+        match &self.listen {
+            FrontendListen::Tcp(_) => Ok(Upstream::Http(format!("http://localhost:{}", self.pinned()?))),
+            #[cfg(unix)]
+            FrontendListen::Unix(socket) => Ok(Upstream::Unix(socket.path().to_path_buf())),
+        }
+    }
+
+    /// Where the frontend accepts connections; known once the first launch succeeded
+    pub fn ready_address(&self) -> Result<FrontendAddress> {
+        match &self.mode {
+            LaunchMode::External { .. } => match self.upstream()? {
+                Upstream::Http(url) => url_address(&url),
+                #[cfg(unix)]
+                Upstream::Unix(path) => Ok(FrontendAddress::Unix(path)),
+            },
+            _ => match &self.listen {
+                FrontendListen::Tcp(_) => Ok(self.tcp_address(self.pinned()?)),
+                #[cfg(unix)]
+                FrontendListen::Unix(socket) => Ok(FrontendAddress::Unix(socket.path().to_path_buf())),
+            },
+        }
+    }
+
+    /// Spawns the frontend (unless it is external) and blocks until it is ready.
+    ///
+    /// When another process takes the allocated port before the frontend binds
+    /// it, the launch is retried on another port.
+    pub fn launch(&self) -> Result<FrontendProcess> {
+        if let LaunchMode::External { url } = &self.mode {
+            info!("Waiting for external frontend at {}", url);
+            let tls = url.starts_with("https:");
+            let address = self.ready_address()?;
+            let timeout = self.ready_timeout;
+            wait_until_ready(&address, &self.ready_path, tls, timeout, &self.shutdown, None)?;
+            return Ok(FrontendProcess::external());
+        }
+
+        let mut tried = Vec::new();
+        loop {
+            let address = self.next_address(&tried)?;
+            let error = match self.spawn(&address) {
+                Ok(process) => {
+                    if let FrontendAddress::Tcp { port, .. } = address {
+                        *self.pinned_port.lock().expect("pinned port lock poisoned") = Some(port);
+                    }
+                    return Ok(process);
+                }
+                Err(e) => e,
+            };
+
+            // Only an unpinned port that something else now holds is worth another try
+            if *self.shutdown.borrow() {
+                return Err(error);
+            }
+            let FrontendListen::Tcp(ports) = &self.listen else {
+                return Err(error);
+            };
+            let FrontendAddress::Tcp { port, .. } = address else {
+                return Err(error);
+            };
+            let pinned = self.pinned_port.lock().expect("pinned port lock poisoned").is_some();
+            if pinned || !ports.can_retry() || tried.len() + 1 >= PORT_ATTEMPTS || port::is_free(port) {
+                return Err(error);
+            }
+
+            warn!("Frontend port {} was taken by another process, trying another port", port);
+            tried.push(port);
+        }
+    }
+
+    fn pinned(&self) -> Result<u16> {
+        self.pinned_port
+            .lock()
+            .expect("pinned port lock poisoned")
+            .context("The frontend has not been launched yet")
+    }
+
+    /// Vite binds whichever address `localhost` resolves to first; production frontends bind IPv4
+    fn tcp_address(&self, port: u16) -> FrontendAddress {
+        let host = match self.mode {
+            LaunchMode::ViteDev { .. } => "localhost",
+            _ => "127.0.0.1",
+        };
+        FrontendAddress::Tcp {
+            host: host.to_string(),
+            port,
+        }
+    }
+
+    fn next_address(&self, tried: &[u16]) -> Result<FrontendAddress> {
+        match &self.listen {
+            FrontendListen::Tcp(ports) => {
+                let pinned = *self.pinned_port.lock().expect("pinned port lock poisoned");
+                let port = match pinned {
+                    Some(port) => port,
+                    None => ports.allocate(tried)?,
+                };
+                Ok(self.tcp_address(port))
+            }
+            #[cfg(unix)]
+            FrontendListen::Unix(socket) => {
+                socket.remove_stale();
+                Ok(FrontendAddress::Unix(socket.path().to_path_buf()))
+            }
+        }
+    }
+
+    fn spawn(&self, address: &FrontendAddress) -> Result<FrontendProcess> {
+        // Otherwise whatever holds the port would answer the readiness probe in the frontend's place
+        if let FrontendAddress::Tcp { port, .. } = address
+            && !port::is_free(*port)
+        {
+            anyhow::bail!("Frontend port {} is already in use by another process", port);
+        }
+
+        let mut command = self.command(address)?;
+        self.sandbox.apply(&mut command);
+        info!("Starting frontend on {}: {:?}", address, self.mode);
+        let mut child = bind_to_parent(&mut command)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .with_context(|| format!("Failed to spawn frontend ({:?})", self.mode))?;
+
+        info!("Frontend started with PID: {:?}", child.id());
+
+        let recent_output = RecentOutput::default();
+        if let Some(stdout) = child.stdout.take() {
+            forward_output(stdout, self.log_target, false, recent_output.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            forward_output(stderr, self.log_target, true, recent_output.clone());
+        }
+
+        let mut process = FrontendProcess::new(child);
+        if self.sandbox.process_group() {
+            process = process.with_process_group();
+        }
+        // Dropping the process on failure stops it, so nothing is left half-started
+        wait_until_ready(
+            address,
+            &self.ready_path,
+            false,
+            self.ready_timeout,
+            &self.shutdown,
+            Some(&mut process),
+        )
+        .map_err(|e| recent_output.append_to(e))?;
+
+        // A process that exited right after becoming ready is treated as a failed launch
+        if let Some(status) = process.try_wait()? {
+            anyhow::bail!("Frontend exited right after starting with {}", status);
+        }
+
+        // Another process may still have taken the port between the check above and the
+        // frontend binding it, in which case it answered the probe instead. Only Linux
+        // can tell whose socket that is, so elsewhere this goes unnoticed
+        #[cfg(target_os = "linux")]
+        if let FrontendAddress::Tcp { port, .. } = address
+            && let Some(pid) = process.id()
+            && port::is_held_by(pid, *port) == Some(false)
+        {
+            anyhow::bail!("Frontend port {} was answered by another process, not the frontend", port);
+        }
+
+        Ok(process)
+    }
+
+    fn command(&self, address: &FrontendAddress) -> Result<Command> {
+        let command = match &self.mode {
+            LaunchMode::Binary {
+                executable,
+                working_dir,
+            } => {
+                let mut command = Command::new(executable);
+                if let Some(dir) = working_dir {
+                    command.current_dir(dir);
+                }
+                production_env(&mut command, address);
+                command
+            }
+            LaunchMode::Bun { bundle } => {
+                let mut command = Command::new("bun");
+                command.arg(bundle);
+                if let Some(dir) = bundle.parent() {
+                    command.current_dir(dir);
+                }
+                production_env(&mut command, address);
+                command
+            }
+            LaunchMode::ViteDev { client_dir } => {
+                let FrontendAddress::Tcp { port, .. } = address else {
+                    anyhow::bail!("The Vite dev server can only listen on a TCP port");
+                };
+                let mut command = Command::new("bun");
+                command
+                    .args(["run", "dev", "--port", &port.to_string(), "--strictPort"])
+                    .current_dir(client_dir);
+                command
+            }
+            LaunchMode::External { .. } => anyhow::bail!("External frontends are not spawned"),
+        };
+
+        Ok(command)
+    }
+}
+
+/// Host and port a frontend URL connects to, defaulting the port from the scheme
+fn url_address(url: &str) -> Result<FrontendAddress> {
+    let uri: Uri = url
+        .parse()
+        .with_context(|| format!("Invalid frontend URL {:?}", url))?;
+    let host = uri
+        .host()
+        .with_context(|| format!("Frontend URL {:?} has no host", url))?;
+    let port = uri.port_u16().unwrap_or(match uri.scheme_str() {
+        Some("https") => 443,
+        _ => 80,
+    });
+
+    Ok(FrontendAddress::Tcp {
+        host: host.trim_start_matches('[').trim_end_matches(']').to_string(),
+        port,
+    })
+}
+
+/// `SOCKET_PATH` follows adapter-node's convention for listening on a Unix socket; frontends
+/// that ignore it are only usable over TCP.
+///
+/// `HOST` stays on loopback for sockets too, so a frontend that ignores `SOCKET_PATH` falls
+/// back to its default port on 127.0.0.1 instead of on every interface.
+fn production_env(command: &mut Command, address: &FrontendAddress) {
+    command.env("HOST", "127.0.0.1");
+    match address {
+        FrontendAddress::Tcp { port, .. } => {
+            command.env("PORT", port.to_string());
+        }
+        #[cfg(unix)]
+        FrontendAddress::Unix(path) => {
+            command.env("SOCKET_PATH", path).env_remove("PORT");
+        }
+    }
+    command.env("NODE_ENV", "production");
+}
+
+/// Polls the ready path until it answers 2xx, failing early if the process exits or
+/// shutdown starts.
+fn wait_until_ready(
+    address: &FrontendAddress,
+    ready_path: &str,
+    tls: bool,
+    timeout: Duration,
+    shutdown: &watch::Receiver<bool>,
+    mut process: Option<&mut FrontendProcess>,
+) -> Result<()> {
+    info!("Waiting for frontend to be ready on {}{}...", address, ready_path);
+    let start = Instant::now();
+
+    loop {
+        // The probe speaks plain HTTP only; TLS frontends are ready once their port accepts connections
+        let probe = match (tls, address) {
+            (true, FrontendAddress::Tcp { host, port }) => {
+                TcpStream::connect((host.as_str(), *port)).map(|_| None)
+            }
+            _ => probe::http_status(address, ready_path, PROBE_TIMEOUT).map(Some),
+        };
+        let last_probe = match probe {
+            Ok(None) => break,
+            Ok(Some(status)) if (200..300).contains(&status) => break,
+            Ok(Some(status)) => format!("{} answered {}", ready_path, status),
+            Err(e) => e.to_string(),
+        };
+
+        if let Some(process) = process.as_deref_mut()
+            && let Some(status) = process.try_wait()?
+        {
+            anyhow::bail!("Frontend exited with {} before becoming ready", status);
+        }
+        if *shutdown.borrow() {
+            anyhow::bail!("Shutdown started before the frontend was ready");
+        }
+        if start.elapsed() >= timeout {
+            anyhow::bail!(
+                "Frontend was not ready within {} seconds (last probe: {})",
+                timeout.as_secs(),
+                last_probe
+            );
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+
+    info!("Frontend is ready after {:?}", start.elapsed());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+
+    /// Answers each connection with the next status, repeating the last one
+    fn frontend(statuses: &'static [u16]) -> FrontendAddress {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            for (i, stream) in listener.incoming().enumerate() {
+                let mut stream = stream.unwrap();
+                // Read the request head so closing the socket does not reset it
+                let mut reader = BufReader::new(&stream);
+                let mut line = String::new();
+                while reader.read_line(&mut line).is_ok_and(|read| read > 2) {
+                    line.clear();
+                }
+                let status = statuses[i.min(statuses.len() - 1)];
+                write!(
+                    stream,
+                    "HTTP/1.1 {} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                )
+                .ok();
+            }
+        });
+        FrontendAddress::Tcp {
+            host: "127.0.0.1".to_string(),
+            port,
+        }
+    }
+
+    /// A shutdown channel that never fires
+    fn running() -> watch::Receiver<bool> {
+        watch::channel(false).1
+    }
+
+    /// A port nothing listens on, because it was just released
+    fn closed_port() -> FrontendAddress {
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        FrontendAddress::Tcp {
+            host: "127.0.0.1".to_string(),
+            port,
+        }
+    }
+
+    /// Flips the returned channel to true after `delay`
+    fn shutdown_after(delay: Duration) -> watch::Receiver<bool> {
+        let (shutdown_tx, shutdown) = watch::channel(false);
+        thread::spawn(move || {
+            thread::sleep(delay);
+            shutdown_tx.send_replace(true);
+        });
+        shutdown
+    }
+
+    #[test]
+    fn ready_once_the_probe_answers_2xx() {
+        let address = frontend(&[503, 200]);
+        let timeout = Duration::from_secs(5);
+        wait_until_ready(&address, "/", false, timeout, &running(), None).unwrap();
+    }
+
+    #[test]
+    fn times_out_when_the_probe_never_succeeds() {
+        let address = frontend(&[503]);
+        let started = Instant::now();
+        let timeout = Duration::from_millis(500);
+        let error = wait_until_ready(&address, "/health", false, timeout, &running(), None)
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.contains("not ready"), "{}", error);
+        assert!(error.contains("/health answered 503"), "{}", error);
+        assert!(started.elapsed() < Duration::from_secs(3));
+    }
+
+    #[test]
+    fn fails_early_when_the_process_exits() {
+        let address = closed_port();
+        let child = Command::new("sh").args(["-c", "exit 3"]).spawn().unwrap();
+        let mut process = FrontendProcess::new(child);
+
+        let timeout = Duration::from_secs(30);
+        let started = Instant::now();
+        let process = Some(&mut process);
+        let error = wait_until_ready(&address, "/", false, timeout, &running(), process)
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.contains("exited"), "{}", error);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn stops_waiting_once_shutdown_starts() {
+        let address = closed_port();
+        let shutdown = shutdown_after(Duration::from_millis(300));
+
+        let started = Instant::now();
+        let timeout = Duration::from_secs(30);
+        let error = wait_until_ready(&address, "/", false, timeout, &shutdown, None)
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.contains("Shutdown started"), "{}", error);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn stops_a_half_started_frontend_on_shutdown() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("launcher-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let script = dir.join("frontend.sh");
+        let pid_file = dir.join("pid");
+        // Never listens, so it would only be given up on after the ready timeout
+        let body = format!("#!/bin/sh\necho $$ > {:?}\nexec sleep 30\n", pid_file);
+        std::fs::write(&script, body).unwrap();
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let launcher = FrontendLauncher::new(LaunchMode::Binary {
+            executable: script,
+            working_dir: None,
+        })
+        .with_ready_timeout(Duration::from_secs(30))
+        .with_shutdown(shutdown_after(Duration::from_millis(500)));
+
+        let started = Instant::now();
+        let error = launcher.launch().err().expect("launch fails").to_string();
+        assert!(error.contains("Shutdown started"), "{}", error);
+        assert!(started.elapsed() < Duration::from_secs(10));
+
+        let pid: libc::pid_t = std::fs::read_to_string(&pid_file)
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+        // SAFETY: kill(2) with signal 0 only checks whether the process exists
+        let alive = unsafe { libc::kill(pid, 0) } == 0;
+        assert!(!alive, "frontend {} is still running", pid);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn socket_frontends_keep_a_loopback_host() {
+        use std::ffi::OsStr;
+
+        let mut command = Command::new("frontend");
+        command.env("PORT", "3000");
+        production_env(
+            &mut command,
+            &FrontendAddress::Unix("/run/app/frontend.sock".into()),
+        );
+
+        let env: Vec<_> = command.get_envs().collect();
+        assert!(env.contains(&(
+            OsStr::new("SOCKET_PATH"),
+            Some(OsStr::new("/run/app/frontend.sock"))
+        )));
+        assert!(env.contains(&(OsStr::new("HOST"), Some(OsStr::new("127.0.0.1")))));
+        assert!(
+            env.contains(&(OsStr::new("PORT"), None)),
+            "PORT is removed, not inherited"
+        );
+    }
+
+    /// Runs the real SvelteKit build with Bun and checks that svelte-adapter-bun serves on
+    /// `SOCKET_PATH`. Needs `bun` and `bun run build` in apps/client, so it is skipped without them.
+    #[cfg(unix)]
+    #[test]
+    fn bundled_frontend_listens_on_socket_path() {
+        let build = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("../client/build");
+        let has_bun = Command::new("bun").arg("--version").output().is_ok();
+        if !has_bun || !build.join("index.js").exists() {
+            eprintln!("skipped: needs bun and a client build in {:?}", build);
+            return;
+        }
+
+        let dir = std::env::temp_dir().join(format!("launcher-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        let address = FrontendAddress::Unix(dir.join("frontend.sock"));
+        let mut command = Command::new("bun");
+        command
+            .arg("index.js")
+            .current_dir(&build)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        production_env(&mut command, &address);
+        let mut process = FrontendProcess::new(command.spawn().unwrap());
+
+        let ready = wait_until_ready(
+            &address,
+            "/",
+            false,
+            Duration::from_secs(30),
+            &running(),
+            Some(&mut process),
+        );
+        drop(process);
+        std::fs::remove_dir_all(&dir).ok();
+        ready.unwrap();
+    }
+}
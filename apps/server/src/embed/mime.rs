@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+
+/// Extension to MIME type table; text types carry an explicit UTF-8 charset
+const MIME_TYPES: &[(&str, &str)] = &[
+    // Documents and text
+    ("html", "text/html; charset=utf-8"),
+    ("htm", "text/html; charset=utf-8"),
+    ("css", "text/css; charset=utf-8"),
+    ("txt", "text/plain; charset=utf-8"),
+    ("csv", "text/csv; charset=utf-8"),
+    ("md", "text/markdown; charset=utf-8"),
+    ("ics", "text/calendar; charset=utf-8"),
+    ("vtt", "text/vtt; charset=utf-8"),
+    ("xml", "application/xml; charset=utf-8"),
+    ("rss", "application/rss+xml; charset=utf-8"),
+    ("atom", "application/atom+xml; charset=utf-8"),
+    ("yaml", "application/yaml; charset=utf-8"),
+    ("yml", "application/yaml; charset=utf-8"),
+    // Scripts and data
+    ("js", "text/javascript; charset=utf-8"),
+    ("mjs", "text/javascript; charset=utf-8"),
+    ("cjs", "text/javascript; charset=utf-8"),
+    ("json", "application/json"),
+    ("map", "application/json"),
+    ("jsonld", "application/ld+json"),
+    ("webmanifest", "application/manifest+json"),
+    ("wasm", "application/wasm"),
+    // Images
+    ("svg", "image/svg+xml"),
+    ("png", "image/png"),
+    ("apng", "image/apng"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("gif", "image/gif"),
+    ("webp", "image/webp"),
+    ("avif", "image/avif"),
+    ("jxl", "image/jxl"),
+    ("bmp", "image/bmp"),
+    ("tif", "image/tiff"),
+    ("tiff", "image/tiff"),
+    ("ico", "image/x-icon"),
+    // Fonts
+    ("woff", "font/woff"),
+    ("woff2", "font/woff2"),
+    ("ttf", "font/ttf"),
+    ("otf", "font/otf"),
+    ("eot", "application/vnd.ms-fontobject"),
+    // Audio and video
+    ("mp4", "video/mp4"),
+    ("m4v", "video/mp4"),
+    ("webm", "video/webm"),
+    ("ogv", "video/ogg"),
+    ("mov", "video/quicktime"),
+    ("mp3", "audio/mpeg"),
+    ("m4a", "audio/mp4"),
+    ("aac", "audio/aac"),
+    ("oga", "audio/ogg"),
+    ("ogg", "audio/ogg"),
+    ("opus", "audio/opus"),
+    ("wav", "audio/wav"),
+    ("flac", "audio/flac"),
+    // Archives and downloads
+    ("pdf", "application/pdf"),
+    ("zip", "application/zip"),
+    ("gz", "application/gzip"),
+    ("tar", "application/x-tar"),
+    ("7z", "application/x-7z-compressed"),
+];
+
+const DEFAULT_MIME_TYPE: &str = "application/octet-stream";
+
+/// MIME type lookup over the built-in table, with custom mappings that take precedence
+#[derive(Debug, Clone, Default)]
+pub struct MimeTypes {
+    overrides: HashMap<String, String>,
+}
+
+impl MimeTypes {
+    /// Uses custom mappings from lowercase extensions (without the dot) to MIME types,
+    /// as validated by the `[assets]` config section.
+    pub fn new(overrides: impl IntoIterator<Item = (String, String)>) -> Self {
+        Self {
+            overrides: overrides.into_iter().collect(),
+        }
+    }
+
+    /// Looks up the MIME type for a path by its file extension, ignoring case
+    pub fn mime_type<'a>(&'a self, path: &str) -> &'a str {
+        let file_name = path.rsplit('/').next().unwrap_or(path);
+        // The leading dot of a dotfile such as `.env` does not start an extension
+        let Some((_, ext)) = file_name
+            .rsplit_once('.')
+            .filter(|(stem, _)| !stem.is_empty())
+        else {
+            return DEFAULT_MIME_TYPE;
+        };
+
+        if !self.overrides.is_empty()
+            && let Some(mime) = self.overrides.get(&ext.to_ascii_lowercase())
+        {
+            return mime;
+        }
+
+        MIME_TYPES
+            .iter()
+            .find(|(known, _)| known.eq_ignore_ascii_case(ext))
+            .map(|(_, mime)| *mime)
+            .unwrap_or(DEFAULT_MIME_TYPE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn knows_modern_web_formats() {
+        let mime_types = MimeTypes::default();
+        let expected = [
+            ("image.webp", "image/webp"),
+            ("image.avif", "image/avif"),
+            ("video.mp4", "video/mp4"),
+            ("module.wasm", "application/wasm"),
+            ("site.webmanifest", "application/manifest+json"),
+            ("robots.txt", "text/plain; charset=utf-8"),
+            ("sitemap.xml", "application/xml; charset=utf-8"),
+            ("app.js.map", "application/json"),
+            ("chunk.mjs", "text/javascript; charset=utf-8"),
+        ];
+        for (path, mime) in expected {
+            assert_eq!(mime_types.mime_type(path), mime, "{}", path);
+        }
+    }
+
+    #[test]
+    fn ignores_extension_case() {
+        let mime_types = MimeTypes::default();
+        assert_eq!(mime_types.mime_type("/_app/IMAGE.WEBP"), "image/webp");
+        assert_eq!(mime_types.mime_type("Index.Html"), "text/html; charset=utf-8");
+    }
+
+    #[test]
+    fn text_types_carry_a_charset() {
+        let mime_types = MimeTypes::default();
+        for (ext, mime) in MIME_TYPES {
+            if mime.starts_with("text/") {
+                assert!(mime.ends_with("; charset=utf-8"), "{}", ext);
+            }
+        }
+        assert_eq!(mime_types.mime_type("logo.png"), "image/png");
+    }
+
+    #[test]
+    fn overrides_take_precedence() {
+        let mime_types = MimeTypes::new([
+            ("glb".to_string(), "model/gltf-binary".to_string()),
+            ("txt".to_string(), "text/plain; charset=latin1".to_string()),
+        ]);
+        assert_eq!(mime_types.mime_type("scene.GLB"), "model/gltf-binary");
+        assert_eq!(mime_types.mime_type("notes.txt"), "text/plain; charset=latin1");
+        assert_eq!(mime_types.mime_type("page.html"), "text/html; charset=utf-8");
+    }
+
+    #[test]
+    fn falls_back_without_an_extension() {
+        let mime_types = MimeTypes::default();
+        for path in ["LICENSE", "/assets/.env", ".html", "/v1.2/download", "archive."] {
+            assert_eq!(mime_types.mime_type(path), DEFAULT_MIME_TYPE, "{}", path);
+        }
+    }
+}
@@ -1,3 +1,5 @@
+use crate::config::FrontendConfig;
+
 #[cfg(debug_assertions)]
 pub mod dev;
 
@@ -9,25 +11,91 @@ pub mod frontend;
 #[cfg(not(bun_compile))]
 pub mod bun_runtime;
 #[cfg(not(debug_assertions))]
+mod extract;
+#[cfg(not(debug_assertions))]
+#[cfg(all(bun_compile, target_os = "linux"))]
+mod memfd;
+#[cfg(not(debug_assertions))]
+pub mod mime;
+#[cfg(not(debug_assertions))]
+mod ranges;
+#[cfg(not(debug_assertions))]
 pub mod static_assets;
+pub mod launcher;
+mod output;
+mod port;
+mod probe;
+pub mod process;
+mod sandbox;
+#[cfg(unix)]
+mod socket;
+pub mod supervisor;
 
-#[allow(unused)]
-#[cfg(debug_assertions)]
-pub use dev::{run_dev_server, DevServer};
+#[cfg(not(debug_assertions))]
+pub use mime::MimeTypes;
+#[cfg(not(debug_assertions))]
+pub use static_assets::{AssetsLayer, StaticAsset};
 
-// #[cfg(not(debug_assertions))]
-// #[cfg(bun_compile)]
-// pub fn run_frontend(frontend_port: u16) -> anyhow::Result<()> {
-// 	frontend::run_frontend_binary(frontend_port)
-// }
+pub use launcher::{FrontendAddress, FrontendLauncher, LaunchMode};
+pub use supervisor::{FrontendState, FrontendStatus, FrontendSupervisor, RestartPolicy};
 
-#[cfg(not(debug_assertions))]
-#[cfg(bun_compile)]
-pub use frontend::run_frontend_binary as run_frontend;
+/// Picks the launcher for this build, or an external frontend when `frontend.url` is set.
+pub fn create_launcher(config: &FrontendConfig) -> anyhow::Result<FrontendLauncher> {
+    let mut launcher = match &config.url {
+        Some(url) => FrontendLauncher::new(LaunchMode::External { url: url.clone() }),
+        None => build_launcher(config)?,
+    };
+    launcher = launcher.with_ready_path(&config.ready_path);
+    if let Some(target) = &config.log_target {
+        launcher = launcher.with_log_target(target);
+    }
 
-#[cfg(not(debug_assertions))]
-#[cfg(not(bun_compile))]
-pub use bun_runtime::run_frontend_bun as run_frontend;
+    Ok(launcher.with_ready_timeout(config.ready_timeout()))
+}
+
+fn build_launcher(config: &FrontendConfig) -> anyhow::Result<FrontendLauncher> {
+    let sandbox = sandbox::Sandbox::from_config(&config.sandbox)?;
+
+    #[cfg(debug_assertions)]
+    return Ok(dev::frontend_launcher(config.dev_port)?.with_sandbox(sandbox.for_dev_server()));
+
+    #[cfg(not(debug_assertions))]
+    #[cfg(bun_compile)]
+    let launcher = frontend::frontend_launcher(config.extract_dir.as_deref(), sandbox.credentials())?;
 
+    #[cfg(not(debug_assertions))]
+    #[cfg(not(bun_compile))]
+    let launcher =
+        bun_runtime::frontend_launcher(config.extract_dir.as_deref(), sandbox.credentials())?;
+
+    #[cfg(not(debug_assertions))]
+    return Ok(launcher
+        .with_listen(production_listen(config, &sandbox)?)
+        .with_sandbox(sandbox));
+}
+
+/// Where the production frontend listens, from `frontend.transport`, `port` and `port_range`
 #[cfg(not(debug_assertions))]
-pub use static_assets::AssetsLayer;
+fn production_listen(
+    config: &FrontendConfig,
+    sandbox: &sandbox::Sandbox,
+) -> anyhow::Result<launcher::FrontendListen> {
+    use launcher::FrontendListen;
+    use port::PortAllocation;
+
+    #[cfg(unix)]
+    if config.transport == crate::config::FrontendTransport::Unix {
+        let socket = socket::FrontendSocket::create(sandbox.credentials())?;
+        return Ok(FrontendListen::Unix(std::sync::Arc::new(socket)));
+    }
+
+    let ports = match (config.port, config.port_range) {
+        (Some(port), _) => PortAllocation::Fixed(port),
+        (None, Some(range)) => PortAllocation::Range {
+            start: range.start,
+            end: range.end,
+        },
+        (None, None) => PortAllocation::Ephemeral,
+    };
+    Ok(FrontendListen::Tcp(ports))
+}
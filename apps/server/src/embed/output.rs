@@ -0,0 +1,750 @@
+use serde_json::{Map, Value as JsonValue};
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use std::time::Duration;
+use tracing::field::Value;
+use tracing_core::{
+    callsite::{self, Callsite},
+    dispatcher,
+    field::{Field, FieldSet},
+    metadata::Kind,
+    subscriber::Interest,
+    Event, Level, Metadata,
+};
+
+/// How long a pending event waits for continuation lines before it is logged
+const GROUP_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Lines folded into one event at most; anything beyond starts a new event
+const MAX_GROUPED_LINES: usize = 200;
+
+const LEVELS: [Level; 5] = [Level::TRACE, Level::DEBUG, Level::INFO, Level::WARN, Level::ERROR];
+
+/// Lines of output kept for startup errors
+const RECENT_LINES: usize = 20;
+
+/// Fields of frontend events without a callsite of their own; `fields` joins the
+/// extra keys of JSON log lines into one string
+const FIELDS: &[&str] = &["message", "fields"];
+
+/// JSON log lines with at most this many extra keys log each key as a field of its own
+const MAX_JSON_FIELDS: usize = 16;
+
+/// Sets of JSON keys per target that get their own callsites. Callsites are never
+/// freed, so lines with further key sets fall back to the joined `fields` string
+const MAX_KEY_SETS: usize = 64;
+
+/// Tracing target the frontend's output is logged under.
+///
+/// `tracing` macros need a literal target, so events are dispatched through
+/// callsites created at runtime instead. Callsites are created once per target
+/// name and shared by every launch, so restarts do not leak. JSON log lines get
+/// a callsite per level and set of keys, so their keys become separate fields.
+#[derive(Clone, Copy)]
+pub struct LogTarget {
+    name: &'static str,
+    callsites: &'static [&'static OutputCallsite; 5],
+    keyed: &'static Mutex<HashMap<(usize, Vec<String>), &'static OutputCallsite>>,
+}
+
+impl LogTarget {
+    pub fn new(name: &str) -> Self {
+        static TARGETS: OnceLock<Mutex<HashMap<String, LogTarget>>> = OnceLock::new();
+
+        let mut targets = TARGETS
+            .get_or_init(Default::default)
+            .lock()
+            .expect("log target registry lock poisoned");
+        *targets.entry(name.to_string()).or_insert_with(|| {
+            let name: &'static str = Box::leak(name.into());
+            LogTarget {
+                name,
+                callsites: Box::leak(Box::new(
+                    LEVELS.map(|level| OutputCallsite::register(name, level, FIELDS)),
+                )),
+                keyed: Box::leak(Box::default()),
+            }
+        })
+    }
+
+    fn emit(&self, event: &LogEvent) {
+        let index = LEVELS.iter().position(|level| *level == event.level).unwrap_or(2);
+        if !event.fields.is_empty()
+            && let Some(callsite) = self.keyed_callsite(index, &event.fields)
+        {
+            self.emit_keyed(callsite.metadata(), event);
+            return;
+        }
+        let metadata = self.callsites[index].metadata();
+
+        dispatcher::get_default(|dispatch| {
+            if !dispatch.enabled(metadata) {
+                return;
+            }
+            let fields = metadata.fields();
+            let (Some(message), Some(extra)) = (fields.field("message"), fields.field("fields"))
+            else {
+                return;
+            };
+            let joined = (!event.fields.is_empty()).then(|| {
+                event
+                    .fields
+                    .iter()
+                    .map(|(key, value)| format!("{}={}", key, value))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            });
+            let values = [
+                (&message, Some(&event.message as &dyn Value)),
+                (&extra, joined.as_ref().map(|joined| joined as &dyn Value)),
+            ];
+            dispatch.event(&Event::new(metadata, &fields.value_set(&values)));
+        });
+    }
+
+    /// The callsite for this level and set of JSON keys, unless there are too many
+    fn keyed_callsite(
+        &self,
+        index: usize,
+        fields: &[(String, JsonValue)],
+    ) -> Option<&'static OutputCallsite> {
+        // A `message` key would clash with the message field itself
+        if fields.len() > MAX_JSON_FIELDS || fields.iter().any(|(key, _)| key == "message") {
+            return None;
+        }
+
+        let keys: Vec<String> = fields.iter().map(|(key, _)| key.clone()).collect();
+        let key = (index, keys);
+        let mut keyed = self.keyed.lock().expect("log callsite registry lock poisoned");
+        if let Some(callsite) = keyed.get(&key) {
+            return Some(callsite);
+        }
+        if keyed.len() >= MAX_KEY_SETS {
+            return None;
+        }
+
+        let names: Vec<&'static str> = std::iter::once("message")
+            .chain(key.1.iter().map(|name| &*name.clone().leak()))
+            .collect();
+        let callsite = OutputCallsite::register(self.name, LEVELS[index], names.leak());
+        keyed.insert(key, callsite);
+        Some(callsite)
+    }
+
+    /// Logs the message and each JSON key as fields of a callsite made for those keys
+    fn emit_keyed(&self, metadata: &'static Metadata<'static>, event: &LogEvent) {
+        dispatcher::get_default(|dispatch| {
+            if !dispatch.enabled(metadata) {
+                return;
+            }
+            let fields = metadata.fields();
+            let names: Vec<Field> = fields.iter().collect();
+            let values: Vec<FieldValue<'_>> = event
+                .fields
+                .iter()
+                .map(|(_, value)| FieldValue::from(value))
+                .collect();
+
+            // value_set needs an array; entries past the last field are skipped as empty
+            let mut set: [(&Field, Option<&dyn Value>); MAX_JSON_FIELDS + 1] =
+                [(&names[0], None); MAX_JSON_FIELDS + 1];
+            set[0].1 = Some(&event.message as &dyn Value);
+            for (i, value) in values.iter().enumerate() {
+                set[i + 1] = (&names[i + 1], Some(value.as_value()));
+            }
+            dispatch.event(&Event::new(metadata, &fields.value_set(&set)));
+        });
+    }
+}
+
+/// A JSON value as the closest type `tracing` records natively
+enum FieldValue<'a> {
+    Str(&'a str),
+    I64(i64),
+    U64(u64),
+    F64(f64),
+    Bool(bool),
+    /// Objects, arrays and null, as JSON text
+    Json(String),
+}
+
+impl<'a> From<&'a JsonValue> for FieldValue<'a> {
+    fn from(value: &'a JsonValue) -> Self {
+        match value {
+            JsonValue::String(s) => FieldValue::Str(s),
+            JsonValue::Bool(b) => FieldValue::Bool(*b),
+            JsonValue::Number(n) => {
+                if let Some(n) = n.as_u64() {
+                    FieldValue::U64(n)
+                } else if let Some(n) = n.as_i64() {
+                    FieldValue::I64(n)
+                } else {
+                    FieldValue::F64(n.as_f64().unwrap_or(f64::NAN))
+                }
+            }
+            other => FieldValue::Json(other.to_string()),
+        }
+    }
+}
+
+impl FieldValue<'_> {
+    fn as_value(&self) -> &dyn Value {
+        match self {
+            FieldValue::Str(s) => s,
+            FieldValue::I64(n) => n,
+            FieldValue::U64(n) => n,
+            FieldValue::F64(n) => n,
+            FieldValue::Bool(b) => b,
+            FieldValue::Json(s) => s,
+        }
+    }
+}
+
+impl fmt::Debug for LogTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("LogTarget").field(&self.name).finish()
+    }
+}
+
+#[derive(Default)]
+struct OutputCallsite {
+    metadata: OnceLock<Metadata<'static>>,
+}
+
+impl OutputCallsite {
+    /// Creates and registers a callsite that lives for the rest of the process
+    fn register(
+        target: &'static str,
+        level: Level,
+        fields: &'static [&'static str],
+    ) -> &'static OutputCallsite {
+        let callsite: &'static OutputCallsite = Box::leak(Box::default());
+        let metadata = Metadata::new(
+            "frontend output",
+            target,
+            level,
+            None,
+            None,
+            None,
+            FieldSet::new(fields, tracing_core::identify_callsite!(callsite)),
+            Kind::EVENT,
+        );
+        callsite.metadata.get_or_init(|| metadata);
+        callsite::register(callsite);
+        callsite
+    }
+}
+
+impl Callsite for OutputCallsite {
+    fn set_interest(&self, _interest: Interest) {}
+
+    fn metadata(&self) -> &Metadata<'static> {
+        self.metadata.get().expect("callsite metadata is set on creation")
+    }
+}
+
+/// The last lines a child printed on either pipe, for explaining failed launches
+#[derive(Clone, Default)]
+pub struct RecentOutput(Arc<Mutex<VecDeque<String>>>);
+
+impl RecentOutput {
+    fn push(&self, line: &str) {
+        let mut lines = self.0.lock().expect("recent output lock poisoned");
+        if lines.len() == RECENT_LINES {
+            lines.pop_front();
+        }
+        lines.push_back(line.to_string());
+    }
+
+    /// Adds the recorded lines to a launch error
+    pub fn append_to(&self, error: anyhow::Error) -> anyhow::Error {
+        // Output of a process that just exited may still be on its way through the pipe
+        thread::sleep(GROUP_TIMEOUT);
+
+        let lines = self.0.lock().expect("recent output lock poisoned");
+        if lines.is_empty() {
+            return anyhow::anyhow!("{:#}; the frontend printed no output", error);
+        }
+        let lines: Vec<_> = lines.iter().map(String::as_str).collect();
+        anyhow::anyhow!("{:#}; last frontend output:\n  {}", error, lines.join("\n  "))
+    }
+}
+
+/// One log event, possibly built from several lines of output
+#[derive(Debug)]
+struct LogEvent {
+    level: Level,
+    message: String,
+    /// Extra keys of a JSON log line, sorted by key
+    fields: Vec<(String, JsonValue)>,
+    lines: usize,
+}
+
+impl LogEvent {
+    /// Parses a line as JSON or as text with a level prefix, falling back to `default_level`
+    fn parse(line: &str, default_level: Level) -> Self {
+        if let Some(event) = Self::parse_json(line, default_level) {
+            return event;
+        }
+
+        let (level, message) = level_prefix(line).unwrap_or((default_level, line));
+        LogEvent {
+            level,
+            message: message.to_string(),
+            fields: Vec::new(),
+            lines: 1,
+        }
+    }
+
+    /// Structured loggers such as pino: `level` and `msg` become the event, the rest its fields
+    fn parse_json(line: &str, default_level: Level) -> Option<Self> {
+        if !line.trim_start().starts_with('{') {
+            return None;
+        }
+        let mut object: Map<String, JsonValue> = serde_json::from_str(line).ok()?;
+
+        let level = ["level", "severity", "lvl"]
+            .iter()
+            .find_map(|key| object.remove(*key))
+            .and_then(|level| match level {
+                JsonValue::String(name) => level_from_name(&name),
+                // pino's numeric levels
+                JsonValue::Number(n) => n.as_u64().map(|n| match n {
+                    0..=10 => Level::TRACE,
+                    11..=20 => Level::DEBUG,
+                    21..=30 => Level::INFO,
+                    31..=40 => Level::WARN,
+                    _ => Level::ERROR,
+                }),
+                _ => None,
+            })
+            .unwrap_or(default_level);
+        let message = match ["msg", "message"].iter().find_map(|key| object.remove(*key)) {
+            Some(JsonValue::String(message)) => message,
+            Some(other) => other.to_string(),
+            None => String::new(),
+        };
+        // Our own timestamp is the one that matters
+        object.remove("time");
+        object.remove("timestamp");
+
+        // Sorted, so lines with the same keys in another order share a callsite
+        let mut fields: Vec<(String, JsonValue)> = object.into_iter().collect();
+        fields.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        Some(LogEvent {
+            level,
+            message,
+            fields,
+            lines: 1,
+        })
+    }
+
+    fn append(&mut self, line: &str) {
+        self.message.push('\n');
+        self.message.push_str(line);
+        self.lines += 1;
+    }
+}
+
+/// Stack frames, `Caused by:` chains and the rest of multi-line dumps belong to the previous line
+fn is_continuation(line: &str) -> bool {
+    line.starts_with([' ', '\t'])
+        || line.starts_with("Caused by")
+        || line.starts_with("[cause]")
+        || line.starts_with('}')
+}
+
+/// Recognizes `[WARN] msg`, `warn: msg`, `WARN msg` and `TypeError: msg`
+fn level_prefix(line: &str) -> Option<(Level, &str)> {
+    let trimmed = line.trim_start();
+
+    if let Some(rest) = trimmed.strip_prefix('[')
+        && let Some((name, rest)) = rest.split_once(']')
+        && let Some(level) = level_from_name(name.trim())
+    {
+        return Some((level, rest.trim_start_matches(':').trim_start()));
+    }
+
+    let end = trimmed
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(trimmed.len());
+    let (word, rest) = trimmed.split_at(end);
+
+    // Uncaught exceptions keep their name in the message
+    if (word.ends_with("Error") || word.ends_with("Exception")) && rest.starts_with(':') {
+        return Some((Level::ERROR, trimmed));
+    }
+
+    // Bare words only count in capitals, so "error handling" stays a plain line
+    if let Some(level) = level_from_name(word)
+        && (rest.starts_with(':')
+            || (rest.starts_with(' ') && word.bytes().all(|b| b.is_ascii_uppercase())))
+    {
+        return Some((level, rest.trim_start_matches(':').trim_start()));
+    }
+
+    None
+}
+
+fn level_from_name(name: &str) -> Option<Level> {
+    match name.to_ascii_lowercase().as_str() {
+        "trace" => Some(Level::TRACE),
+        "debug" | "verbose" => Some(Level::DEBUG),
+        "info" | "notice" => Some(Level::INFO),
+        "warn" | "warning" => Some(Level::WARN),
+        "error" | "err" | "fatal" | "critical" | "panic" => Some(Level::ERROR),
+        _ => None,
+    }
+}
+
+/// Streams a child pipe into tracing, remembering the last lines in `recent`.
+///
+/// Lines are read on one thread and turned into events on another, so a stack
+/// trace can be grouped with the line before it and still be logged promptly
+/// when no further output follows.
+pub fn forward_output<R>(
+    pipe: R,
+    target: LogTarget,
+    is_stderr: bool,
+    recent: RecentOutput,
+) where
+    R: Read + Send + 'static,
+{
+    let (lines_tx, lines_rx) = mpsc::channel::<String>();
+
+    thread::spawn(move || {
+        let reader = BufReader::new(pipe);
+        // Split on raw bytes so a line that is not UTF-8 doesn't end the loop
+        for line in reader.split(b'\n').map_while(Result::ok) {
+            let line = String::from_utf8_lossy(&line);
+            let cleaned = strip_ansi_codes(line.trim_end_matches('\r'));
+            if cleaned.trim().is_empty() {
+                continue;
+            }
+
+            recent.push(&cleaned);
+            if lines_tx.send(cleaned).is_err() {
+                break;
+            }
+        }
+    });
+
+    let default_level = if is_stderr { Level::WARN } else { Level::INFO };
+    thread::spawn(move || group_lines(lines_rx, default_level, |event| target.emit(&event)));
+}
+
+/// Folds continuation lines into the event before them and hands every finished event to `emit`
+fn group_lines(
+    lines_rx: mpsc::Receiver<String>,
+    default_level: Level,
+    mut emit: impl FnMut(LogEvent),
+) {
+    let mut pending: Option<LogEvent> = None;
+
+    loop {
+        let line = match &pending {
+            Some(_) => lines_rx.recv_timeout(GROUP_TIMEOUT),
+            None => lines_rx.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+
+        match line {
+            Ok(line) => {
+                if let Some(event) = &mut pending
+                    && event.lines < MAX_GROUPED_LINES
+                    && is_continuation(&line)
+                {
+                    event.append(&line);
+                    continue;
+                }
+                if let Some(event) = pending.replace(LogEvent::parse(&line, default_level)) {
+                    emit(event);
+                }
+            }
+            Err(RecvTimeoutError::Timeout) => {
+                if let Some(event) = pending.take() {
+                    emit(event);
+                }
+            }
+            Err(RecvTimeoutError::Disconnected) => {
+                if let Some(event) = pending.take() {
+                    emit(event);
+                }
+                break;
+            }
+        }
+    }
+}
+
+/// Removes ANSI escape sequences (CSI such as colors, and OSC such as hyperlinks) from a line.
+fn strip_ansi_codes(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+
+    while let Some(ch) = chars.next() {
+        if ch != '\u{1b}' {
+            result.push(ch);
+            continue;
+        }
+
+        match chars.next() {
+            // CSI: parameters and intermediates, terminated by a byte in '@'..='~'
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('@'..='~').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // OSC: terminated by BEL or by ST (ESC \)
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\u{7}' {
+                        break;
+                    }
+                    if c == '\u{1b}' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            // Two-character escape sequences; nothing else to skip
+            _ => {}
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+
+    /// An event's level and its fields as text
+    type Recorded = (Level, Vec<(String, String)>);
+
+    /// Records every event
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<Recorded>>>);
+
+    impl<S: tracing::Subscriber> tracing_subscriber::Layer<S> for Recorder {
+        fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+            let mut fields = FieldRecorder(Vec::new());
+            event.record(&mut fields);
+            let level = *event.metadata().level();
+            self.0.lock().unwrap().push((level, fields.0));
+        }
+    }
+
+    struct FieldRecorder(Vec<(String, String)>);
+
+    impl tracing::field::Visit for FieldRecorder {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.push((field.name().to_string(), value.to_string()));
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            let value = format!("{:?}", value);
+            self.0.push((field.name().to_string(), value));
+        }
+    }
+
+    /// Emits lines of JSON through a fresh target and returns what a subscriber saw
+    fn emitted(lines: &[&str]) -> Vec<Recorded> {
+        let recorder = Recorder::default();
+        let subscriber = tracing_subscriber::registry().with(recorder.clone());
+        let target = LogTarget::new(&format!("test-{}", uuid::Uuid::new_v4().simple()));
+        tracing::subscriber::with_default(subscriber, || {
+            for line in lines {
+                target.emit(&LogEvent::parse(line, Level::INFO));
+            }
+        });
+        recorder.0.lock().unwrap().clone()
+    }
+
+    fn pairs(fields: &[(&str, &str)]) -> Vec<(String, String)> {
+        fields
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    /// Runs lines through the grouping and returns the events it produced
+    fn grouped(lines: &[&str]) -> Vec<LogEvent> {
+        let (tx, rx) = mpsc::channel();
+        for line in lines {
+            tx.send(line.to_string()).unwrap();
+        }
+        drop(tx);
+        let mut events = Vec::new();
+        group_lines(rx, Level::INFO, |event| events.push(event));
+        events
+    }
+
+    #[test]
+    fn keeps_reading_after_invalid_utf8() {
+        let pipe = std::io::Cursor::new(b"first\n\xff\xfe broken\nlast\n".to_vec());
+        let recent = RecentOutput::default();
+        forward_output(pipe, LogTarget::new("test"), false, recent.clone());
+
+        let lines = (0..100)
+            .find_map(|_| {
+                thread::sleep(Duration::from_millis(10));
+                let lines = recent.0.lock().unwrap().clone();
+                (lines.len() == 3).then_some(lines)
+            })
+            .expect("all lines forwarded");
+        assert_eq!(lines, ["first", "\u{fffd}\u{fffd} broken", "last"]);
+    }
+
+    #[test]
+    fn strips_csi_sequences() {
+        let colored = "\x1b[1;32mVITE\x1b[0m ready";
+        assert_eq!(strip_ansi_codes(colored), "VITE ready");
+        assert_eq!(strip_ansi_codes("\x1b[2K\x1b[1Gdone"), "done");
+    }
+
+    #[test]
+    fn strips_osc_sequences() {
+        // Hyperlinks end with BEL, window titles here with ST
+        assert_eq!(
+            strip_ansi_codes("see \x1b]8;;http://localhost\x07docs\x1b]8;;\x07 page"),
+            "see docs page"
+        );
+        assert_eq!(strip_ansi_codes("\x1b]0;bun\x1b\\started"), "started");
+    }
+
+    #[test]
+    fn drops_unterminated_escapes() {
+        assert_eq!(strip_ansi_codes("progress \x1b[12"), "progress ");
+        assert_eq!(strip_ansi_codes("title \x1b]0;never ends"), "title ");
+        assert_eq!(strip_ansi_codes("end\x1b"), "end");
+        assert_eq!(strip_ansi_codes("\x1b7kept\x1b8"), "kept");
+    }
+
+    #[test]
+    fn maps_pino_numeric_levels() {
+        let levels = [
+            (10, Level::TRACE),
+            (20, Level::DEBUG),
+            (30, Level::INFO),
+            (40, Level::WARN),
+            (50, Level::ERROR),
+            (60, Level::ERROR),
+        ];
+        for (number, level) in levels {
+            let line = format!(r#"{{"level":{},"time":1700000000000,"msg":"hi"}}"#, number);
+            let event = LogEvent::parse(&line, Level::INFO);
+            assert_eq!(event.level, level, "{}", number);
+            assert_eq!(event.message, "hi");
+            assert!(event.fields.is_empty());
+        }
+    }
+
+    #[test]
+    fn reads_level_prefixes() {
+        let expected = [
+            ("[WARN] disk almost full", Level::WARN, "disk almost full"),
+            ("[error]: failed", Level::ERROR, "failed"),
+            ("warn: deprecated option", Level::WARN, "deprecated option"),
+            ("WARN deprecated option", Level::WARN, "deprecated option"),
+            ("  DEBUG: cache warm", Level::DEBUG, "cache warm"),
+        ];
+        for (line, level, message) in expected {
+            assert_eq!(level_prefix(line), Some((level, message)), "{}", line);
+        }
+    }
+
+    #[test]
+    fn keeps_exception_names_in_the_message() {
+        for line in [
+            "TypeError: Cannot read properties of undefined (reading 'id')",
+            "SyntaxError: Unexpected token",
+            "NullPointerException: at startup",
+        ] {
+            assert_eq!(level_prefix(line), Some((Level::ERROR, line)));
+        }
+    }
+
+    #[test]
+    fn leaves_plain_lines_alone() {
+        assert_eq!(level_prefix("error handling is enabled"), None);
+        assert_eq!(level_prefix("Warn users before logout"), None);
+        assert_eq!(level_prefix("Listening on http://localhost:3000"), None);
+        assert_eq!(level_prefix("[vite] connected"), None);
+    }
+
+    #[test]
+    fn groups_stack_traces_with_their_error() {
+        let events = grouped(&[
+            "TypeError: boom",
+            "    at load (/app/build/server/index.js:10:5)",
+            "    at async render (/app/build/server/index.js:20:3)",
+            "Caused by: Error: inner",
+            "Listening on :3000",
+        ]);
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].level, Level::ERROR);
+        assert_eq!(events[0].lines, 4);
+        let message = &events[0].message;
+        let head = "TypeError: boom\n    at load";
+        assert!(message.starts_with(head), "{}", message);
+        assert!(events[0].message.ends_with("Caused by: Error: inner"));
+        assert_eq!(events[1].message, "Listening on :3000");
+        assert_eq!(events[1].level, Level::INFO);
+    }
+
+    #[test]
+    fn caps_grouped_lines() {
+        let mut lines = vec!["Error: deep"];
+        lines.extend(std::iter::repeat_n("    at frame", MAX_GROUPED_LINES + 10));
+        let events = grouped(&lines);
+
+        // The rest starts a new event rather than growing the first without bound
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].lines, MAX_GROUPED_LINES);
+        assert_eq!(events[1].lines, 11);
+    }
+
+    #[test]
+    fn emits_json_keys_as_separate_fields() {
+        let events = emitted(&[
+            r#"{"level":40,"msg":"slow request","req":{"id":7},"status":200,"path":"/a"}"#,
+            r#"{"status":500,"level":"error","path":"/b","req":null,"msg":"failed"}"#,
+        ]);
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].0, Level::WARN);
+        let first = [
+            ("message", "slow request"),
+            ("path", "/a"),
+            ("req", r#"{"id":7}"#),
+            ("status", "200"),
+        ];
+        assert_eq!(events[0].1, pairs(&first));
+        // Same keys in another order, through the same callsite
+        let second = [
+            ("message", "failed"),
+            ("path", "/b"),
+            ("req", "null"),
+            ("status", "500"),
+        ];
+        assert_eq!(events[1].0, Level::ERROR);
+        assert_eq!(events[1].1, pairs(&second));
+    }
+
+    #[test]
+    fn joins_keys_that_cannot_be_fields() {
+        let events = emitted(&[r#"{"msg":"hi","message":"again","n":1}"#, "plain line"]);
+
+        let joined = [("message", "hi"), ("fields", r#"message="again" n=1"#)];
+        assert_eq!(events[0].1, pairs(&joined));
+        assert_eq!(events[1].1, pairs(&[("message", "plain line")]));
+    }
+}
@@ -0,0 +1,165 @@
+use anyhow::{Context, Result};
+use std::net::{Ipv4Addr, TcpListener};
+
+/// How the TCP port of a spawned frontend is chosen
+///
+/// The frontend binds the port itself, and neither Bun nor the SvelteKit adapters can take
+/// over an already bound socket, so `Range` and `Ephemeral` only pick a port that is free
+/// when checked. That narrows the race with other processes but does not close it; see
+/// [`PortAllocation::allocate`]. The Unix socket transport avoids it altogether.
+///
+/// Each build only constructs the strategies that apply to it.
+#[allow(dead_code)]
+#[derive(Debug, Clone)]
+pub enum PortAllocation {
+    /// Exactly this port; if something else holds it, the launch fails
+    Fixed(u16),
+    /// The first port in this inclusive range that is free
+    Range { start: u16, end: u16 },
+    /// A port the OS reports free: it is bound to port 0 and released again before the
+    /// frontend starts, so it is a likely-free port rather than a reserved one
+    Ephemeral,
+}
+
+impl PortAllocation {
+    /// Picks a port that is free right now, skipping ones already tried.
+    ///
+    /// Nothing holds the port between this check and the frontend binding it, so another
+    /// process can still win the race. When the frontend then fails to bind, the launcher
+    /// retries with another port. When the other process answers the readiness probe
+    /// instead, only Linux notices, through [`is_held_by`]; elsewhere the proxy would talk
+    /// to the wrong process until the frontend is restarted.
+    pub fn allocate(&self, tried: &[u16]) -> Result<u16> {
+        match self {
+            PortAllocation::Fixed(port) => Ok(*port),
+            PortAllocation::Range { start, end } => (*start..=*end)
+                .filter(|port| !tried.contains(port))
+                .find(|port| is_free(*port))
+                .with_context(|| format!("No free frontend port left in {}-{}", start, end)),
+            PortAllocation::Ephemeral => {
+                let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+                    .context("Failed to find a free port for the frontend")?;
+                Ok(listener.local_addr()?.port())
+            }
+        }
+    }
+
+    /// Whether another port may be tried after losing one to another process
+    pub fn can_retry(&self) -> bool {
+        !matches!(self, PortAllocation::Fixed(_))
+    }
+}
+
+/// Whether the port can currently be bound on loopback
+pub fn is_free(port: u16) -> bool {
+    TcpListener::bind((Ipv4Addr::LOCALHOST, port)).is_ok()
+}
+
+/// Whether the socket listening on `port` belongs to process `pid` or one of its
+/// descendants, such as the Vite server `bun run dev` starts; `None` when that
+/// cannot be told.
+///
+/// The kernel lists listening sockets in /proc/net/tcp and tcp6, and every
+/// process's descriptors under /proc/PID/fd link to the sockets they hold. Other
+/// systems have no such listing, so the check only exists on Linux.
+#[cfg(target_os = "linux")]
+pub fn is_held_by(pid: u32, port: u16) -> Option<bool> {
+    use std::collections::HashSet;
+    use std::fs;
+
+    let mut listening = HashSet::new();
+    for table in ["/proc/net/tcp", "/proc/net/tcp6"] {
+        let Ok(table) = fs::read_to_string(table) else {
+            continue;
+        };
+        for line in table.lines().skip(1) {
+            let fields: Vec<_> = line.split_whitespace().collect();
+            let (Some(local), Some(&"0A"), Some(inode)) = (fields.get(1), fields.get(3), fields.get(9))
+            else {
+                continue;
+            };
+            if local.rsplit(':').next().and_then(|p| u16::from_str_radix(p, 16).ok()) == Some(port) {
+                listening.insert(format!("socket:[{}]", inode));
+            }
+        }
+    }
+    if listening.is_empty() {
+        return None;
+    }
+
+    let held = process_tree(pid).into_iter().any(|pid| {
+        fs::read_dir(format!("/proc/{}/fd", pid))
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|fd| fs::read_link(fd.path()).ok())
+            .any(|target| listening.contains(target.to_string_lossy().as_ref()))
+    });
+    Some(held)
+}
+
+/// `pid` and all of its descendants
+#[cfg(target_os = "linux")]
+fn process_tree(pid: u32) -> Vec<u32> {
+    let parents: Vec<(u32, u32)> = std::fs::read_dir("/proc")
+        .into_iter()
+        .flatten()
+        .flatten()
+        .filter_map(|entry| {
+            let pid = entry.file_name().to_str()?.parse().ok()?;
+            let stat = std::fs::read_to_string(entry.path().join("stat")).ok()?;
+            // The command name may contain spaces; the parent PID is the second field after it
+            let ppid = stat.rsplit_once(')')?.1.split_whitespace().nth(1)?.parse().ok()?;
+            Some((pid, ppid))
+        })
+        .collect();
+
+    let mut tree = vec![pid];
+    let mut next = 0;
+    while let Some(&parent) = tree.get(next) {
+        tree.extend(parents.iter().filter(|(_, ppid)| *ppid == parent).map(|(pid, _)| *pid));
+        next += 1;
+    }
+    tree
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_skip_ports_in_use_and_ports_tried() {
+        let held = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = held.local_addr().unwrap().port();
+        let ports = PortAllocation::Range { start: port, end: port };
+        assert!(ports.allocate(&[]).is_err());
+
+        drop(held);
+        assert_eq!(ports.allocate(&[]).unwrap(), port);
+        assert!(ports.allocate(&[port]).is_err());
+    }
+
+    #[test]
+    fn ephemeral_ports_are_released_for_the_frontend() {
+        let port = PortAllocation::Ephemeral.allocate(&[]).unwrap();
+        assert_ne!(port, 0);
+        // Nothing keeps the port bound, which is what leaves the race open
+        assert!(is_free(port));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn tells_whose_socket_listens_on_a_port() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+        assert_eq!(is_held_by(std::process::id(), port), Some(true));
+
+        let mut other = std::process::Command::new("sleep").arg("5").spawn().unwrap();
+        assert_eq!(is_held_by(other.id(), port), Some(false));
+        other.kill().ok();
+        other.wait().ok();
+
+        drop(listener);
+        assert_eq!(is_held_by(std::process::id(), port), None);
+    }
+}
@@ -0,0 +1,63 @@
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use super::launcher::FrontendAddress;
+
+/// Sends `GET path` over plain HTTP/1.1 and returns the response status code.
+pub fn http_status(address: &FrontendAddress, path: &str, timeout: Duration) -> io::Result<u16> {
+    match address {
+        FrontendAddress::Tcp { host, port } => {
+            let stream = connect_tcp(host, *port, timeout)?;
+            stream.set_read_timeout(Some(timeout))?;
+            stream.set_write_timeout(Some(timeout))?;
+            request_status(stream, &address.to_string(), path)
+        }
+        #[cfg(unix)]
+        FrontendAddress::Unix(socket) => {
+            let stream = std::os::unix::net::UnixStream::connect(socket)?;
+            stream.set_read_timeout(Some(timeout))?;
+            stream.set_write_timeout(Some(timeout))?;
+            request_status(stream, "localhost", path)
+        }
+    }
+}
+
+/// Tries every address the host resolves to in turn, so a dev server that only
+/// bound `::1` is still found through `localhost`.
+fn connect_tcp(host: &str, port: u16, timeout: Duration) -> io::Result<TcpStream> {
+    let mut last_error = io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("{} did not resolve to any address", host),
+    );
+    for addr in (host, port).to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, timeout) {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = e,
+        }
+    }
+
+    Err(last_error)
+}
+
+fn request_status<S: Read + Write>(mut stream: S, authority: &str, path: &str) -> io::Result<u16> {
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: {}-ready-probe\r\nAccept: */*\r\nConnection: close\r\n\r\n",
+        path,
+        authority,
+        env!("CARGO_PKG_NAME")
+    )?;
+
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line)?;
+
+    let mut parts = status_line.split_whitespace();
+    match (parts.next(), parts.next().and_then(|code| code.parse().ok())) {
+        (Some(version), Some(status)) if version.starts_with("HTTP/") => Ok(status),
+        _ => Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("not an HTTP response: {:?}", status_line.trim()),
+        )),
+    }
+}
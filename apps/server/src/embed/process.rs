@@ -0,0 +1,152 @@
+use std::io;
+use std::process::{Child, Command, ExitStatus};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// How long a frontend process gets to exit after SIGTERM before it is killed
+const TERMINATE_GRACE: Duration = Duration::from_secs(5);
+
+/// Owns a spawned frontend process and terminates it when stopped or dropped.
+pub struct FrontendProcess {
+    child: Option<Child>,
+    /// Signals go to the process group the child leads, reaching everything it started
+    group_leader: bool,
+}
+
+impl FrontendProcess {
+    pub fn new(child: Child) -> Self {
+        Self {
+            child: Some(child),
+            group_leader: false,
+        }
+    }
+
+    /// A handle for a frontend that runs outside this process and is never signalled
+    pub fn external() -> Self {
+        Self {
+            child: None,
+            group_leader: false,
+        }
+    }
+
+    /// Stop the child's whole process group; only for children spawned as group leaders
+    pub fn with_process_group(mut self) -> Self {
+        self.group_leader = true;
+        self
+    }
+
+    /// PID of the running child; `None` for external frontends and reaped children
+    #[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+    pub fn id(&self) -> Option<u32> {
+        self.child.as_ref().map(Child::id)
+    }
+
+    /// Returns the exit status if the process has exited, releasing it so it is not signalled again.
+    pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        let Some(child) = self.child.as_mut() else {
+            return Ok(None);
+        };
+
+        let status = child.try_wait()?;
+        if status.is_some() {
+            self.child = None;
+        }
+
+        Ok(status)
+    }
+
+    /// Asks the process to exit, escalating to a kill if it is still running after the grace period.
+    pub fn stop(&mut self) {
+        let Some(mut child) = self.child.take() else {
+            return;
+        };
+
+        info!("Stopping frontend process (PID {})", child.id());
+
+        #[cfg(unix)]
+        let target = match self.group_leader {
+            true => -(child.id() as libc::pid_t),
+            false => child.id() as libc::pid_t,
+        };
+
+        #[cfg(unix)]
+        {
+            // SAFETY: kill(2) has no memory-safety preconditions; the PID belongs to our own
+            // child, which is not reaped yet, so neither it nor its group ID can be reused.
+            unsafe {
+                libc::kill(target, libc::SIGTERM);
+            }
+
+            let start = Instant::now();
+            while start.elapsed() < TERMINATE_GRACE {
+                match child.try_wait() {
+                    Ok(Some(status)) => {
+                        info!("Frontend process exited with {}", status);
+                        return;
+                    }
+                    Ok(None) => thread::sleep(Duration::from_millis(50)),
+                    Err(e) => {
+                        warn!("Failed to poll frontend process: {}", e);
+                        break;
+                    }
+                }
+            }
+
+            warn!(
+                "Frontend process did not exit within {:?}, killing it",
+                TERMINATE_GRACE
+            );
+            if self.group_leader {
+                // SAFETY: as above
+                unsafe {
+                    libc::kill(target, libc::SIGKILL);
+                }
+            }
+        }
+
+        if let Err(e) = child.kill() {
+            warn!("Failed to kill frontend process: {}", e);
+        } else {
+            child.wait().ok();
+            info!("Frontend process stopped");
+        }
+    }
+}
+
+impl Drop for FrontendProcess {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+/// Ties the lifetime of the spawned process to the current one.
+///
+/// On Linux the child receives SIGTERM when the parent dies, even if the parent
+/// is killed without a chance to clean up. This is a no-op on other platforms.
+///
+/// The signal is delivered when the *thread* that spawned the child exits, so the
+/// command must be spawned from a thread that lives as long as the server.
+pub fn bind_to_parent(command: &mut Command) -> &mut Command {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::process::CommandExt;
+
+        let parent_pid = std::process::id();
+        // SAFETY: the closure only calls async-signal-safe functions (prctl, getppid).
+        unsafe {
+            command.pre_exec(move || {
+                if libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGTERM) == -1 {
+                    return Err(std::io::Error::last_os_error());
+                }
+                // The parent may have exited before prctl took effect
+                if libc::getppid() as u32 != parent_pid {
+                    return Err(std::io::Error::from_raw_os_error(libc::ESRCH));
+                }
+                Ok(())
+            });
+        }
+    }
+
+    command
+}
@@ -0,0 +1,287 @@
+use axum::body::Bytes;
+use std::ops::Range;
+use std::time::SystemTime;
+
+/// Upper bound on ranges honoured in one request; more than this serves the full body instead
+const MAX_RANGES: usize = 16;
+
+/// Outcome of evaluating a `Range` header against a body of known length
+#[derive(Debug, PartialEq, Eq)]
+pub enum ByteRanges {
+    /// One or more satisfiable ranges, sorted with overlapping and adjacent ones merged
+    Satisfiable(Vec<Range<usize>>),
+    /// Syntactically valid, but no range overlaps the body (answered with 416)
+    Unsatisfiable,
+}
+
+/// Parses a `bytes=` range header (RFC 9110 §14.1.2).
+///
+/// Returns `None` when the header must be ignored and the full body served:
+/// other units, malformed specs, too many ranges, or ranges that together ask for more
+/// than the whole body (such as `bytes=0-,0-`), which would only amplify the response.
+pub fn parse_range(value: &str, len: usize) -> Option<ByteRanges> {
+    let specs = value.trim().strip_prefix("bytes=")?;
+
+    let mut ranges = Vec::new();
+    let mut count = 0;
+    for spec in specs.split(',') {
+        let spec = spec.trim();
+        if spec.is_empty() {
+            continue;
+        }
+
+        count += 1;
+        if count > MAX_RANGES {
+            return None;
+        }
+
+        let (start, end) = spec.split_once('-')?;
+        let (start, end) = (start.trim(), end.trim());
+
+        let range = if start.is_empty() {
+            // Suffix range: the last `n` bytes
+            let suffix: usize = end.parse().ok()?;
+            if suffix == 0 || len == 0 {
+                continue;
+            }
+            len.saturating_sub(suffix)..len
+        } else {
+            let start: usize = start.parse().ok()?;
+            let end = if end.is_empty() {
+                usize::MAX
+            } else {
+                let end: usize = end.parse().ok()?;
+                if end < start {
+                    return None;
+                }
+                end
+            };
+
+            if start >= len {
+                continue;
+            }
+            start..end.saturating_add(1).min(len)
+        };
+
+        ranges.push(range);
+    }
+
+    if count == 0 {
+        return None;
+    }
+
+    if ranges.is_empty() {
+        return Some(ByteRanges::Unsatisfiable);
+    }
+    if ranges.iter().map(|range| range.len()).sum::<usize>() > len {
+        return None;
+    }
+
+    Some(ByteRanges::Satisfiable(merge(ranges)))
+}
+
+/// Sorts ranges and coalesces those that overlap or touch
+fn merge(mut ranges: Vec<Range<usize>>) -> Vec<Range<usize>> {
+    ranges.sort_by_key(|range| range.start);
+
+    let mut merged: Vec<Range<usize>> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+
+    merged
+}
+
+/// Evaluates `If-Range`: the range only applies if the validator still matches the current representation.
+///
+/// Entity tags use strong comparison, so weak tags never match; dates must equal `Last-Modified` exactly.
+pub fn if_range_matches(value: &str, etag: &str, last_modified: Option<SystemTime>) -> bool {
+    let value = value.trim();
+    if value.starts_with('"') {
+        return value == etag;
+    }
+    if value.starts_with("W/") {
+        return false;
+    }
+
+    match (httpdate::parse_http_date(value), last_modified) {
+        (Ok(date), Some(last_modified)) => date == last_modified,
+        _ => false,
+    }
+}
+
+/// Builds a `multipart/byteranges` body as a list of chunks.
+///
+/// Each part's data is a slice of `data`, so the embedded bytes are shared rather than copied.
+pub fn multipart_body(
+    data: &Bytes,
+    ranges: &[Range<usize>],
+    content_type: &str,
+    boundary: &str,
+) -> Vec<Bytes> {
+    let total_len = data.len();
+    let mut chunks = Vec::with_capacity(ranges.len() * 2 + 1);
+
+    for range in ranges {
+        chunks.push(Bytes::from(format!(
+            "\r\n--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+            boundary,
+            content_type,
+            range.start,
+            range.end - 1,
+            total_len
+        )));
+        chunks.push(data.slice(range.clone()));
+    }
+    chunks.push(Bytes::from(format!("\r\n--{}--\r\n", boundary)));
+
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn satisfiable(value: &str, len: usize) -> Vec<Range<usize>> {
+        match parse_range(value, len) {
+            Some(ByteRanges::Satisfiable(ranges)) => ranges,
+            other => panic!("{} parsed as {:?}", value, other),
+        }
+    }
+
+    #[test]
+    fn parses_closed_ranges() {
+        assert_eq!(satisfiable("bytes=0-9", 100), vec![0..10]);
+        assert_eq!(satisfiable("bytes= 10 - 19 ", 100), vec![10..20]);
+        // The end is clamped to the body
+        assert_eq!(satisfiable("bytes=90-200", 100), vec![90..100]);
+    }
+
+    #[test]
+    fn parses_open_ended_ranges() {
+        assert_eq!(satisfiable("bytes=40-", 100), vec![40..100]);
+        assert_eq!(satisfiable("bytes=0-", 100), vec![0..100]);
+    }
+
+    #[test]
+    fn parses_suffix_ranges() {
+        assert_eq!(satisfiable("bytes=-10", 100), vec![90..100]);
+        // A suffix longer than the body selects all of it
+        assert_eq!(satisfiable("bytes=-500", 100), vec![0..100]);
+    }
+
+    #[test]
+    fn reports_unsatisfiable_ranges() {
+        for value in [
+            "bytes=100-",
+            "bytes=100-200",
+            "bytes=-0",
+            "bytes=200-300, -0",
+        ] {
+            assert_eq!(
+                parse_range(value, 100),
+                Some(ByteRanges::Unsatisfiable),
+                "{}",
+                value
+            );
+        }
+        assert_eq!(parse_range("bytes=-10", 0), Some(ByteRanges::Unsatisfiable));
+    }
+
+    #[test]
+    fn skips_unsatisfiable_specs_among_satisfiable_ones() {
+        assert_eq!(satisfiable("bytes=200-300, 0-9", 100), vec![0..10]);
+    }
+
+    #[test]
+    fn ignores_malformed_headers() {
+        for value in [
+            "items=0-9",
+            "bytes=",
+            "bytes=abc",
+            "bytes=5",
+            "bytes=9-0",
+            "bytes=0-9, x-y",
+            "bytes=-",
+        ] {
+            assert_eq!(parse_range(value, 100), None, "{}", value);
+        }
+    }
+
+    #[test]
+    fn ignores_too_many_ranges() {
+        let specs: Vec<String> = (0..MAX_RANGES)
+            .map(|i| format!("{}-{}", i * 2, i * 2))
+            .collect();
+        let at_limit = format!("bytes={}", specs.join(","));
+        assert_eq!(satisfiable(&at_limit, 100).len(), MAX_RANGES);
+
+        let over_limit = format!("{},40-40", at_limit);
+        assert_eq!(parse_range(&over_limit, 100), None);
+    }
+
+    #[test]
+    fn merges_overlapping_and_adjacent_ranges() {
+        assert_eq!(satisfiable("bytes=20-29, 0-9", 100), vec![0..10, 20..30]);
+        assert_eq!(satisfiable("bytes=0-9, 10-19", 100), vec![0..20]);
+        assert_eq!(
+            satisfiable("bytes=0-9, 5-14, 30-39", 100),
+            vec![0..15, 30..40]
+        );
+        assert_eq!(satisfiable("bytes=50-59, -50", 100), vec![50..100]);
+    }
+
+    #[test]
+    fn ignores_ranges_that_ask_for_more_than_the_body() {
+        assert_eq!(parse_range("bytes=0-,0-", 100), None);
+        assert_eq!(parse_range("bytes=0-59, 40-99", 100), None);
+        assert_eq!(parse_range("bytes=-60, -60", 100), None);
+    }
+
+    #[test]
+    fn if_range_compares_entity_tags_strongly() {
+        let etag = "\"abc\"";
+        assert!(if_range_matches("\"abc\"", etag, None));
+        assert!(!if_range_matches("\"xyz\"", etag, None));
+        assert!(!if_range_matches("W/\"abc\"", etag, None));
+    }
+
+    #[test]
+    fn if_range_requires_the_exact_modification_date() {
+        let last_modified = SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        let date = httpdate::fmt_http_date(last_modified);
+        assert!(if_range_matches(&date, "\"abc\"", Some(last_modified)));
+
+        let earlier = httpdate::fmt_http_date(last_modified - Duration::from_secs(1));
+        assert!(!if_range_matches(&earlier, "\"abc\"", Some(last_modified)));
+        assert!(!if_range_matches(&date, "\"abc\"", None));
+        assert!(!if_range_matches(
+            "not a date",
+            "\"abc\"",
+            Some(last_modified)
+        ));
+    }
+
+    #[test]
+    fn builds_multipart_bodies_from_slices() {
+        let data = Bytes::from_static(b"abcdefghijklmnopqrstuvwxyz");
+        let chunks = multipart_body(&data, &[0..3, 23..26], "text/plain", "sep");
+
+        assert_eq!(chunks.len(), 5);
+        // Range data points into the original buffer
+        assert_eq!(chunks[1].as_ptr(), data.as_ptr());
+        assert_eq!(chunks[3].as_ptr(), data[23..].as_ptr());
+
+        let body: Vec<u8> = chunks.concat();
+        assert_eq!(
+            String::from_utf8(body).unwrap(),
+            "\r\n--sep\r\nContent-Type: text/plain\r\nContent-Range: bytes 0-2/26\r\n\r\nabc\
+             \r\n--sep\r\nContent-Type: text/plain\r\nContent-Range: bytes 23-25/26\r\n\r\nxyz\
+             \r\n--sep--\r\n"
+        );
+    }
+}
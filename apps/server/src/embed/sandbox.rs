@@ -0,0 +1,379 @@
+use anyhow::Result;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::process::Command;
+
+use crate::config::SandboxConfig;
+
+/// Restrictions applied to a spawned frontend process.
+///
+/// Nothing is restricted by default, so the frontend inherits the server's
+/// environment, limits and privileges unless configured otherwise.
+#[derive(Debug, Clone, Default)]
+pub struct Sandbox {
+    /// Inherited variables passed on; everything is inherited when unset
+    env_allowlist: Option<Vec<String>>,
+    /// RLIMIT_DATA in bytes
+    memory_limit: Option<u64>,
+    /// RLIMIT_NOFILE
+    max_open_files: Option<u64>,
+    /// RLIMIT_CPU in seconds
+    cpu_limit: Option<u64>,
+    process_group: bool,
+    credentials: Option<Credentials>,
+}
+
+/// User and group a frontend runs as after privileges are dropped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Credentials {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl Sandbox {
+    /// Resolves `frontend.sandbox`; the user is only switched when the server runs as root
+    pub fn from_config(config: &SandboxConfig) -> Result<Self> {
+        #[cfg(unix)]
+        let credentials = match &config.user {
+            Some(user) => {
+                let credentials = users::resolve(user, config.group.as_deref())?;
+                // SAFETY: geteuid has no preconditions and cannot fail
+                if unsafe { libc::geteuid() } == 0 {
+                    Some(credentials)
+                } else {
+                    tracing::warn!(
+                        "Not running as root, so the frontend keeps this user instead of {:?}",
+                        user
+                    );
+                    None
+                }
+            }
+            None => None,
+        };
+        #[cfg(not(unix))]
+        let credentials = None;
+
+        Ok(Self {
+            env_allowlist: config.env.clone(),
+            memory_limit: config.memory_limit_mb.map(|mb| mb.saturating_mul(1024 * 1024)),
+            max_open_files: config.max_open_files,
+            cpu_limit: config.cpu_limit_secs,
+            process_group: config.process_group,
+            credentials,
+        })
+    }
+
+    /// Who should own files the frontend needs, when it runs as another user
+    #[cfg_attr(debug_assertions, allow(dead_code))]
+    pub fn credentials(&self) -> Option<Credentials> {
+        self.credentials
+    }
+
+    /// The part of the sandbox that applies to the Vite dev server.
+    ///
+    /// Only the process group is kept: Vite and its file watchers need the
+    /// developer's environment and limits, and must be able to write to the
+    /// client directory as the developer, so nothing else is restricted.
+    #[cfg_attr(not(debug_assertions), allow(dead_code))]
+    pub fn for_dev_server(self) -> Self {
+        let restricted = self.env_allowlist.is_some()
+            || self.memory_limit.is_some()
+            || self.max_open_files.is_some()
+            || self.cpu_limit.is_some()
+            || self.credentials.is_some();
+        if restricted {
+            tracing::warn!(
+                "frontend.sandbox only sets the process group of the dev server; its other restrictions apply to release builds"
+            );
+        }
+
+        Self {
+            process_group: self.process_group,
+            ..Self::default()
+        }
+    }
+
+    /// Whether the frontend leads its own process group
+    pub fn process_group(&self) -> bool {
+        self.process_group
+    }
+
+    /// Applies the restrictions to a fully prepared command.
+    ///
+    /// Variables the launcher set or removed explicitly are kept as they are;
+    /// only inherited ones are filtered by the allowlist.
+    pub fn apply(&self, command: &mut Command) {
+        if let Some(allowlist) = &self.env_allowlist {
+            let explicit: Vec<(OsString, Option<OsString>)> = command
+                .get_envs()
+                .map(|(name, value)| (name.to_owned(), value.map(ToOwned::to_owned)))
+                .collect();
+            let overridden: HashSet<&OsString> = explicit.iter().map(|(name, _)| name).collect();
+
+            command.env_clear();
+            for name in allowlist {
+                if !overridden.contains(&OsString::from(name))
+                    && let Some(value) = std::env::var_os(name)
+                {
+                    command.env(name, value);
+                }
+            }
+            for (name, value) in &explicit {
+                if let Some(value) = value {
+                    command.env(name, value);
+                }
+            }
+        }
+
+        #[cfg(unix)]
+        self.apply_unix(command);
+    }
+
+    #[cfg(unix)]
+    fn apply_unix(&self, command: &mut Command) {
+        use std::os::unix::process::CommandExt;
+
+        if self.process_group {
+            command.process_group(0);
+        }
+        // std also drops supplementary groups when switching away from root
+        if let Some(credentials) = self.credentials {
+            command.gid(credentials.gid).uid(credentials.uid);
+        }
+
+        // Raising the inherited hard limit fails with EPERM without CAP_SYS_RESOURCE,
+        // so larger limits are capped at it
+        let clamp = |resource, name: &str, limit: Option<u64>| {
+            let limit = limit.map(|limit| {
+                let limit = limit as libc::rlim_t;
+                let mut current = libc::rlimit {
+                    rlim_cur: 0,
+                    rlim_max: 0,
+                };
+                // SAFETY: getrlimit only writes into the struct it is given
+                let known = unsafe { libc::getrlimit(resource, &mut current) } == 0;
+                if !known || limit <= current.rlim_max {
+                    return limit;
+                }
+                tracing::warn!(
+                    "frontend.sandbox.{} is above the server's hard limit of {}, using that instead",
+                    name,
+                    current.rlim_max
+                );
+                current.rlim_max
+            });
+            (resource, limit)
+        };
+        let limits = [
+            clamp(libc::RLIMIT_DATA, "memory_limit_mb", self.memory_limit),
+            clamp(libc::RLIMIT_NOFILE, "max_open_files", self.max_open_files),
+            clamp(libc::RLIMIT_CPU, "cpu_limit_secs", self.cpu_limit),
+        ];
+        if limits.iter().all(|(_, limit)| limit.is_none()) {
+            return;
+        }
+        // SAFETY: the closure only calls setrlimit, which is async-signal-safe.
+        unsafe {
+            command.pre_exec(move || {
+                for (resource, limit) in limits {
+                    let Some(limit) = limit else {
+                        continue;
+                    };
+                    let rlimit = libc::rlimit {
+                        rlim_cur: limit,
+                        rlim_max: limit,
+                    };
+                    if libc::setrlimit(resource, &rlimit) != 0 {
+                        return Err(std::io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+    }
+}
+
+/// Looks up users and groups by name or numeric id
+#[cfg(unix)]
+mod users {
+    use anyhow::{Context, Result};
+    use std::ffi::CString;
+
+    use super::Credentials;
+
+    /// The user's uid, with `group` or else the user's primary group
+    pub fn resolve(user: &str, group: Option<&str>) -> Result<Credentials> {
+        let (uid, primary_gid) = match user.parse::<u32>() {
+            Ok(uid) => (uid, lookup_uid(uid)?.unwrap_or(uid)),
+            Err(_) => lookup_user(user)?.with_context(|| format!("Unknown user {:?}", user))?,
+        };
+        let gid = match group {
+            Some(group) => match group.parse::<u32>() {
+                Ok(gid) => gid,
+                Err(_) => lookup_group(group)?.with_context(|| format!("Unknown group {:?}", group))?,
+            },
+            None => primary_gid,
+        };
+
+        Ok(Credentials { uid, gid })
+    }
+
+    fn lookup_user(name: &str) -> Result<Option<(u32, u32)>> {
+        let name = CString::new(name)?;
+        with_buffer(
+            // SAFETY: getpwnam_r writes only into the passwd struct and buffer it is given
+            |pwd, buf, len, result| unsafe { libc::getpwnam_r(name.as_ptr(), pwd, buf, len, result) },
+            |pwd: &libc::passwd| (pwd.pw_uid, pwd.pw_gid),
+        )
+    }
+
+    /// Primary group of a numeric user, if it has a passwd entry
+    fn lookup_uid(uid: u32) -> Result<Option<u32>> {
+        with_buffer(
+            // SAFETY: as above
+            |pwd, buf, len, result| unsafe { libc::getpwuid_r(uid, pwd, buf, len, result) },
+            |pwd: &libc::passwd| pwd.pw_gid,
+        )
+    }
+
+    fn lookup_group(name: &str) -> Result<Option<u32>> {
+        let name = CString::new(name)?;
+        with_buffer(
+            // SAFETY: getgrnam_r writes only into the group struct and buffer it is given
+            |grp, buf, len, result| unsafe { libc::getgrnam_r(name.as_ptr(), grp, buf, len, result) },
+            |grp: &libc::group| grp.gr_gid,
+        )
+    }
+
+    /// Calls a reentrant lookup, growing the string buffer until the entry fits,
+    /// and reads the found entry while the buffer is still alive
+    fn with_buffer<T, R>(
+        lookup: impl Fn(*mut T, *mut libc::c_char, usize, *mut *mut T) -> libc::c_int,
+        read: impl Fn(&T) -> R,
+    ) -> Result<Option<R>> {
+        let mut len = 1024;
+        loop {
+            let mut entry = std::mem::MaybeUninit::<T>::uninit();
+            let mut buf = vec![0 as libc::c_char; len];
+            let mut result = std::ptr::null_mut();
+            match lookup(entry.as_mut_ptr(), buf.as_mut_ptr(), len, &mut result) {
+                0 if result.is_null() => return Ok(None),
+                // SAFETY: a non-null result points at `entry`, which the lookup filled in
+                0 => return Ok(Some(read(unsafe { entry.assume_init_ref() }))),
+                libc::ERANGE if len < 1 << 20 => len *= 4,
+                code => {
+                    return Err(std::io::Error::from_raw_os_error(code))
+                        .context("Failed to look up user or group");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    /// Runs a shell snippet through the sandbox and returns its trimmed stdout
+    fn run(sandbox: &Sandbox, script: &str, envs: &[(&str, &str)]) -> String {
+        let mut command = Command::new("sh");
+        command.args(["-c", script]).envs(envs.iter().copied());
+        sandbox.apply(&mut command);
+        let output = command.output().expect("sandboxed command failed to spawn");
+        assert!(output.status.success(), "{:?}", output);
+        String::from_utf8(output.stdout).unwrap().trim().to_string()
+    }
+
+    fn open_files_hard_limit() -> libc::rlim_t {
+        let mut current = libc::rlimit {
+            rlim_cur: 0,
+            rlim_max: 0,
+        };
+        // SAFETY: getrlimit only writes into the struct it is given
+        assert_eq!(
+            unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut current) },
+            0
+        );
+        current.rlim_max
+    }
+
+    #[test]
+    fn applies_resource_limits() {
+        let sandbox = Sandbox {
+            memory_limit: Some(512 * 1024 * 1024),
+            max_open_files: Some(64),
+            cpu_limit: Some(3600),
+            ..Sandbox::default()
+        };
+
+        assert_eq!(run(&sandbox, "ulimit -n; ulimit -Hn", &[]), "64\n64");
+        assert_eq!(run(&sandbox, "ulimit -t", &[]), "3600");
+        // ulimit -d reports kilobytes
+        assert_eq!(run(&sandbox, "ulimit -d", &[]), "524288");
+    }
+
+    #[test]
+    fn caps_limits_at_the_inherited_hard_limit() {
+        let hard = open_files_hard_limit();
+        if hard == libc::RLIM_INFINITY {
+            return;
+        }
+        let sandbox = Sandbox {
+            max_open_files: Some(hard + 1000),
+            ..Sandbox::default()
+        };
+
+        assert_eq!(run(&sandbox, "ulimit -Hn", &[]), hard.to_string());
+    }
+
+    #[test]
+    fn passes_only_allowed_and_explicit_variables() {
+        let sandbox = Sandbox {
+            env_allowlist: Some(vec!["PATH".to_string(), "PORT".to_string()]),
+            ..Sandbox::default()
+        };
+
+        let env = run(
+            &sandbox,
+            "env",
+            &[("PORT", "4000"), ("SOCKET_PATH", "/tmp/s")],
+        );
+        // sh sets PWD, SHLVL and _ itself
+        let mut names: Vec<&str> = env
+            .lines()
+            .filter_map(|line| line.split_once('='))
+            .map(|(name, _)| name)
+            .filter(|name| !matches!(*name, "PWD" | "SHLVL" | "_"))
+            .collect();
+        names.sort_unstable();
+        assert_eq!(names, ["PATH", "PORT", "SOCKET_PATH"]);
+        assert!(env.lines().any(|line| line == "PORT=4000"));
+    }
+
+    #[test]
+    fn dev_server_keeps_only_the_process_group() {
+        let sandbox = Sandbox {
+            env_allowlist: Some(vec!["PATH".to_string()]),
+            memory_limit: Some(512 * 1024 * 1024),
+            max_open_files: Some(64),
+            cpu_limit: Some(3600),
+            process_group: true,
+            credentials: Some(Credentials {
+                uid: 65534,
+                gid: 65534,
+            }),
+        }
+        .for_dev_server();
+
+        assert!(sandbox.process_group());
+        assert_eq!(sandbox.credentials(), None);
+        let limits = "ulimit -n; ulimit -t; ulimit -d";
+        assert_eq!(
+            run(&sandbox, limits, &[]),
+            run(&Sandbox::default(), limits, &[])
+        );
+        // HOME is inherited although only PATH was allowed
+        let home = std::env::var("HOME").unwrap_or_default();
+        assert_eq!(run(&sandbox, "echo \"$HOME\"", &[]), home);
+    }
+}
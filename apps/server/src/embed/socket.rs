@@ -0,0 +1,66 @@
+use anyhow::{Context, Result};
+use std::os::unix::fs::DirBuilderExt;
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+use super::sandbox::Credentials;
+
+/// File name of the frontend's socket inside its directory
+const SOCKET_NAME: &str = "frontend.sock";
+
+/// Unix socket the frontend listens on, in a directory only this user can enter.
+///
+/// The directory is removed when this is dropped.
+#[derive(Debug)]
+pub struct FrontendSocket {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl FrontendSocket {
+    /// Creates a fresh directory for the socket, owned by `owner` if the frontend runs as another user
+    #[cfg_attr(debug_assertions, allow(dead_code))]
+    pub fn create(owner: Option<Credentials>) -> Result<Self> {
+        let base = std::env::var_os("XDG_RUNTIME_DIR")
+            .map(PathBuf::from)
+            .filter(|dir| dir.is_dir())
+            .unwrap_or_else(std::env::temp_dir);
+        let dir = base.join(format!(
+            "{}-{}",
+            env!("WORKSPACE_NAME"),
+            uuid::Uuid::new_v4().simple()
+        ));
+        // Fails if the path exists, so nothing planted there beforehand is ever used
+        std::fs::DirBuilder::new()
+            .mode(0o700)
+            .create(&dir)
+            .with_context(|| format!("Failed to create frontend socket directory {:?}", dir))?;
+        if let Some(owner) = owner {
+            std::os::unix::fs::lchown(&dir, Some(owner.uid), Some(owner.gid))
+                .with_context(|| format!("Failed to hand {:?} to the frontend's user", dir))?;
+        }
+        let path = dir.join(SOCKET_NAME);
+
+        Ok(Self { dir, path })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Removes the socket a previous launch left behind, so the next one can bind it
+    pub fn remove_stale(&self) {
+        match std::fs::remove_file(&self.path) {
+            Ok(()) => debug!("Removed stale frontend socket {:?}", self.path),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => warn!("Failed to remove stale frontend socket {:?}: {}", self.path, e),
+        }
+    }
+}
+
+impl Drop for FrontendSocket {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).ok();
+        std::fs::remove_dir(&self.dir).ok();
+    }
+}
@@ -1,32 +1,77 @@
-use rust_embed::RustEmbed;
+use rust_embed::{EmbeddedFile, RustEmbed};
 use axum::{
-    body::Body,
-    http::{Request, StatusCode},
+    body::{Body, Bytes},
+    http::{header, HeaderMap, Method, Request, StatusCode},
     response::Response,
 };
 use tower::{Layer, Service};
+use futures_util::stream;
+use std::borrow::Cow;
+use std::convert::Infallible;
+use std::sync::Arc;
 use std::future::Future;
 use std::pin::Pin;
 use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use super::mime::MimeTypes;
+use super::ranges::{self, ByteRanges};
+
+/// Client output of the SvelteKit build: hashed `_app/immutable/*` files plus everything in `static/`
 #[derive(RustEmbed)]
-#[folder = "../client/static"]
+#[folder = "../client/build/client"]
 struct Assets;
 
+/// Files under this prefix carry a content hash in their name and never change
+const IMMUTABLE_PREFIX: &str = "_app/immutable/";
+
+const IMMUTABLE_CACHE_CONTROL: &str = "public, max-age=31536000, immutable";
+const DEFAULT_CACHE_CONTROL: &str = "public, max-age=300";
+
+/// Response extension marking responses served from the embedded assets
+#[derive(Debug, Clone, Copy)]
+pub struct StaticAsset;
+
+/// Looks up an embedded file by its path relative to the build directory
+type GetAsset = fn(&str) -> Option<EmbeddedFile>;
+
 #[derive(Clone)]
-pub struct AssetsLayer;
+pub struct AssetsLayer {
+    mime_types: Arc<MimeTypes>,
+    get: GetAsset,
+}
+
+impl AssetsLayer {
+    pub fn new(mime_types: MimeTypes) -> Self {
+        Self::with_assets::<Assets>(mime_types)
+    }
+
+    /// Serves the files of another embedded folder instead of the client build
+    fn with_assets<A: RustEmbed>(mime_types: MimeTypes) -> Self {
+        Self {
+            mime_types: Arc::new(mime_types),
+            get: A::get,
+        }
+    }
+}
 
 impl<S> Layer<S> for AssetsLayer {
     type Service = AssetsMiddleware<S>;
 
     fn layer(&self, inner: S) -> AssetsMiddleware<S> {
-        AssetsMiddleware { inner }
+        AssetsMiddleware {
+            inner,
+            mime_types: self.mime_types.clone(),
+            get: self.get,
+        }
     }
 }
 
 #[derive(Clone)]
 pub struct AssetsMiddleware<S> {
     inner: S,
+    mime_types: Arc<MimeTypes>,
+    get: GetAsset,
 }
 
 impl<S> Service<Request<Body>> for AssetsMiddleware<S>
@@ -44,38 +89,537 @@ where
 
     fn call(&mut self, req: Request<Body>) -> Self::Future {
         let path = req.uri().path().trim_start_matches('/');
-        
-        // Try to serve from assets first
-        if let Some(asset) = Assets::get(path) {
-            let mime = get_mime_type(path);
+        let is_read = req.method() == Method::GET || req.method() == Method::HEAD;
+
+        // Precompressed siblings are only served through content negotiation on the original path
+        if is_read && is_precompressed_variant(path, self.get) {
             let response = Response::builder()
-                .status(StatusCode::OK)
-                .header("content-type", mime)
-                .body(Body::from(asset.data.to_vec()))
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::empty())
                 .unwrap();
-            
+            return Box::pin(async move { Ok(response) });
+        }
+
+        // Try to serve from assets first
+        if let Some(asset) = (self.get)(path).filter(|_| is_read) {
+            let response = serve_asset(&req, path, asset, self.get, &self.mime_types);
             return Box::pin(async move { Ok(response) });
         }
 
         // Fall through to inner service (proxy, api routes, etc.)
-        let future = self.inner.call(req);
-        Box::pin(async move { future.await })
+        Box::pin(self.inner.call(req))
+    }
+}
+
+fn serve_asset(
+    req: &Request<Body>,
+    path: &str,
+    identity: EmbeddedFile,
+    get: GetAsset,
+    mime_types: &MimeTypes,
+) -> Response {
+    let (asset, encoding, has_variants) = select_variant(req.headers(), path, identity, get);
+    let etag = format_etag(&asset.metadata.sha256_hash());
+    let last_modified = asset
+        .metadata
+        .last_modified()
+        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+
+    let mut response = Response::builder()
+        .extension(StaticAsset)
+        .header(header::ETAG, &etag)
+        .header(header::CACHE_CONTROL, cache_control(path));
+    if has_variants {
+        response = response.header(header::VARY, "Accept-Encoding");
+    }
+    if let Some(encoding) = encoding {
+        response = response.header(header::CONTENT_ENCODING, encoding.name());
+    }
+    if let Some(last_modified) = last_modified {
+        response = response.header(header::LAST_MODIFIED, httpdate::fmt_http_date(last_modified));
+    }
+
+    if is_not_modified(req.headers(), &etag, last_modified) {
+        return response
+            .status(StatusCode::NOT_MODIFIED)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    let data = match asset.data {
+        Cow::Borrowed(data) => Bytes::from_static(data),
+        Cow::Owned(data) => Bytes::from(data),
+    };
+    let total_len = data.len();
+    let content_type = mime_types.mime_type(path);
+    response = response.header(header::ACCEPT_RANGES, "bytes");
+
+    let if_range_ok = req
+        .headers()
+        .get(header::IF_RANGE)
+        .and_then(|v| v.to_str().ok())
+        .is_none_or(|v| ranges::if_range_matches(v, &etag, last_modified));
+    let byte_ranges = req
+        .headers()
+        .get(header::RANGE)
+        .and_then(|v| v.to_str().ok())
+        .filter(|_| if_range_ok)
+        .and_then(|v| ranges::parse_range(v, total_len));
+
+    let (status, content_type, mut chunks) = match byte_ranges {
+        None => (StatusCode::OK, content_type.to_string(), vec![data]),
+        Some(ByteRanges::Unsatisfiable) => {
+            return response
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", total_len))
+                .body(Body::empty())
+                .unwrap();
+        }
+        Some(ByteRanges::Satisfiable(byte_ranges)) if byte_ranges.len() == 1 => {
+            let range = byte_ranges[0].clone();
+            response = response.header(
+                header::CONTENT_RANGE,
+                format!("bytes {}-{}/{}", range.start, range.end - 1, total_len),
+            );
+            // Slicing `Bytes` shares the embedded data instead of copying it
+            (
+                StatusCode::PARTIAL_CONTENT,
+                content_type.to_string(),
+                vec![data.slice(range)],
+            )
+        }
+        Some(ByteRanges::Satisfiable(byte_ranges)) => {
+            let boundary = etag.trim_matches('"');
+            let chunks = ranges::multipart_body(&data, &byte_ranges, content_type, boundary);
+            (
+                StatusCode::PARTIAL_CONTENT,
+                format!("multipart/byteranges; boundary={}", boundary),
+                chunks,
+            )
+        }
+    };
+
+    // HEAD keeps the length of the body a GET would have returned
+    let content_length: usize = chunks.iter().map(Bytes::len).sum();
+    let body = if req.method() == Method::HEAD {
+        Body::empty()
+    } else if chunks.len() == 1 {
+        Body::from(chunks.remove(0))
+    } else {
+        // Multipart parts are streamed one by one, so range data is never copied into one buffer
+        Body::from_stream(stream::iter(chunks.into_iter().map(Ok::<_, Infallible>)))
+    };
+
+    response
+        .status(status)
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CONTENT_LENGTH, content_length)
+        .body(body)
+        .unwrap()
+}
+
+/// Precompressed variants produced by the adapter's `precompress` option, in order of preference
+#[derive(Clone, Copy)]
+enum Encoding {
+    Brotli,
+    Gzip,
+}
+
+impl Encoding {
+    const PREFERRED: [Encoding; 2] = [Encoding::Brotli, Encoding::Gzip];
+
+    fn name(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gz",
+        }
+    }
+}
+
+/// Picks the best precompressed variant the client accepts, falling back to the identity file.
+///
+/// Also reports whether any variant exists, in which case responses must carry `Vary: Accept-Encoding`.
+fn select_variant(
+    headers: &HeaderMap,
+    path: &str,
+    identity: EmbeddedFile,
+    get: GetAsset,
+) -> (EmbeddedFile, Option<Encoding>, bool) {
+    let mut has_variants = false;
+
+    for encoding in Encoding::PREFERRED {
+        let Some(variant) = get(&format!("{}.{}", path, encoding.extension())) else {
+            continue;
+        };
+        has_variants = true;
+
+        if accepts_encoding(headers, encoding.name()) {
+            return (variant, Some(encoding), true);
+        }
+    }
+
+    (identity, None, has_variants)
+}
+
+/// Whether the path names a `.br`/`.gz` file that sits next to the asset it was compressed from
+fn is_precompressed_variant(path: &str, get: GetAsset) -> bool {
+    Encoding::PREFERRED.iter().any(|encoding| {
+        path.strip_suffix(encoding.extension())
+            .and_then(|path| path.strip_suffix('.'))
+            .is_some_and(|original| get(original).is_some())
+    })
+}
+
+/// Whether `Accept-Encoding` lists the coding (or `*`) with a non-zero quality value
+fn accepts_encoding(headers: &HeaderMap, name: &str) -> bool {
+    let Some(accept) = headers
+        .get(header::ACCEPT_ENCODING)
+        .and_then(|v| v.to_str().ok())
+    else {
+        return false;
+    };
+
+    let mut wildcard = false;
+    for item in accept.split(',') {
+        let mut parts = item.split(';');
+        let coding = parts.next().unwrap_or("").trim();
+        let quality = parts
+            .find_map(|p| p.trim().strip_prefix("q="))
+            .and_then(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        if coding.eq_ignore_ascii_case(name) {
+            return quality > 0.0;
+        }
+        if coding == "*" {
+            wildcard = quality > 0.0;
+        }
     }
+
+    wildcard
+}
+
+/// Strong validator derived from the SHA-256 hash rust-embed computes at build time
+fn format_etag(hash: &[u8; 32]) -> String {
+    let hex: String = hash[..16].iter().map(|b| format!("{:02x}", b)).collect();
+    format!("\"{}\"", hex)
+}
+
+/// Evaluates `If-None-Match`, or `If-Modified-Since` when no `If-None-Match` is present (RFC 9110 §13.2.2)
+fn is_not_modified(headers: &HeaderMap, etag: &str, last_modified: Option<SystemTime>) -> bool {
+    if let Some(if_none_match) = headers.get(header::IF_NONE_MATCH) {
+        let Ok(if_none_match) = if_none_match.to_str() else {
+            return false;
+        };
+        // Weak comparison: the W/ prefix is ignored
+        return if_none_match
+            .split(',')
+            .map(|tag| tag.trim())
+            .any(|tag| tag == "*" || tag.trim_start_matches("W/") == etag);
+    }
+
+    let (Some(if_modified_since), Some(last_modified)) =
+        (headers.get(header::IF_MODIFIED_SINCE), last_modified)
+    else {
+        return false;
+    };
+
+    if_modified_since
+        .to_str()
+        .ok()
+        .and_then(|value| httpdate::parse_http_date(value).ok())
+        .is_some_and(|since| last_modified <= since)
 }
 
-fn get_mime_type(path: &str) -> &'static str {
-    match path.split('.').last() {
-        Some("html") => "text/html; charset=utf-8",
-        Some("css") => "text/css",
-        Some("js") => "application/javascript",
-        Some("json") => "application/json",
-        Some("svg") => "image/svg+xml",
-        Some("png") => "image/png",
-        Some("jpg") | Some("jpeg") => "image/jpeg",
-        Some("gif") => "image/gif",
-        Some("ico") => "image/x-icon",
-        Some("woff") => "font/woff",
-        Some("woff2") => "font/woff2",
-        _ => "application/octet-stream",
+fn cache_control(path: &str) -> &'static str {
+    if path.starts_with(IMMUTABLE_PREFIX) {
+        IMMUTABLE_CACHE_CONTROL
+    } else {
+        DEFAULT_CACHE_CONTROL
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    #[derive(RustEmbed)]
+    #[folder = "testdata/assets"]
+    struct Fixture;
+
+    /// Serves the fixture assets in front of a stand-in for the SSR proxy
+    fn app() -> Router {
+        Router::new()
+            .fallback(|| async { "rendered by the frontend" })
+            .layer(AssetsLayer::with_assets::<Fixture>(MimeTypes::new([])))
+    }
+
+    async fn send(request: axum::http::request::Builder) -> (Response, String) {
+        let response = app()
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let (parts, body) = response.into_parts();
+        let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        let body = String::from_utf8_lossy(&body).into_owned();
+        (Response::from_parts(parts, Body::empty()), body)
+    }
+
+    fn header(response: &Response, name: header::HeaderName) -> Option<&str> {
+        response.headers().get(name).map(|v| v.to_str().unwrap())
+    }
+
+    #[tokio::test]
+    async fn caches_immutable_assets_for_a_year() {
+        let (response, body) = send(Request::get("/_app/immutable/app.3f9a1c.js")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body.contains("started"));
+        assert_eq!(
+            header(&response, header::CACHE_CONTROL),
+            Some("public, max-age=31536000, immutable")
+        );
+        assert!(response.extensions().get::<StaticAsset>().is_some());
+    }
+
+    #[tokio::test]
+    async fn caches_other_assets_briefly() {
+        let (response, body) = send(Request::get("/robots.txt")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body.starts_with("User-agent"));
+        assert_eq!(
+            header(&response, header::CACHE_CONTROL),
+            Some("public, max-age=300")
+        );
+    }
+
+    #[tokio::test]
+    async fn passes_other_requests_to_the_frontend() {
+        let requests = [
+            Request::get("/about"),
+            Request::get("/_app/immutable/missing.js"),
+            Request::post("/robots.txt"),
+        ];
+        for request in requests {
+            let (response, body) = send(request).await;
+            assert_eq!(response.status(), StatusCode::OK);
+            assert_eq!(body, "rendered by the frontend");
+            assert!(response.extensions().get::<StaticAsset>().is_none());
+            assert!(header(&response, header::CACHE_CONTROL).is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn revalidates_with_the_etag() {
+        let (response, _) = send(Request::get("/robots.txt")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let etag = header(&response, header::ETAG).unwrap().to_string();
+
+        let (response, body) =
+            send(Request::get("/robots.txt").header(header::IF_NONE_MATCH, &etag)).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(header(&response, header::ETAG), Some(etag.as_str()));
+        assert_eq!(
+            header(&response, header::CACHE_CONTROL),
+            Some("public, max-age=300")
+        );
+        assert!(body.is_empty());
+
+        let weak = format!("\"other\", W/{}", etag);
+        let (response, _) =
+            send(Request::get("/robots.txt").header(header::IF_NONE_MATCH, weak)).await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[tokio::test]
+    async fn revalidates_with_the_modification_date() {
+        let (response, _) = send(Request::get("/robots.txt")).await;
+        let last_modified = header(&response, header::LAST_MODIFIED)
+            .unwrap()
+            .to_string();
+
+        let (response, body) =
+            send(Request::get("/robots.txt").header(header::IF_MODIFIED_SINCE, &last_modified))
+                .await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert!(body.is_empty());
+
+        let before = httpdate::parse_http_date(&last_modified).unwrap() - Duration::from_secs(60);
+        let (response, body) = send(
+            Request::get("/robots.txt")
+                .header(header::IF_MODIFIED_SINCE, httpdate::fmt_http_date(before)),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body.starts_with("User-agent"));
+    }
+
+    #[tokio::test]
+    async fn serves_the_body_when_the_etag_does_not_match() {
+        let (response, body) =
+            send(Request::get("/robots.txt").header(header::IF_NONE_MATCH, "\"stale\"")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(body.starts_with("User-agent"));
+    }
+
+    #[tokio::test]
+    async fn prefers_if_none_match_over_if_modified_since() {
+        let (response, _) = send(Request::get("/robots.txt")).await;
+        let etag = header(&response, header::ETAG).unwrap().to_string();
+        let last_modified = header(&response, header::LAST_MODIFIED)
+            .unwrap()
+            .to_string();
+
+        // A stale tag wins over a date that alone would revalidate
+        let (response, _) = send(
+            Request::get("/robots.txt")
+                .header(header::IF_NONE_MATCH, "\"stale\"")
+                .header(header::IF_MODIFIED_SINCE, &last_modified),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // A matching tag wins over a date that alone would not
+        let (response, _) = send(
+            Request::get("/robots.txt")
+                .header(header::IF_NONE_MATCH, &etag)
+                .header(header::IF_MODIFIED_SINCE, "Thu, 01 Jan 1970 00:00:00 GMT"),
+        )
+        .await;
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    /// Fetches the precompressed fixture, returning its encoding and `Vary` header
+    async fn negotiate(accept_encoding: Option<&str>) -> (Option<String>, Option<String>) {
+        let mut request = Request::get("/_app/immutable/app.3f9a1c.js");
+        if let Some(accept_encoding) = accept_encoding {
+            request = request.header(header::ACCEPT_ENCODING, accept_encoding);
+        }
+        let (response, _) = send(request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            header(&response, header::CONTENT_TYPE),
+            Some("text/javascript; charset=utf-8")
+        );
+        let encoding = header(&response, header::CONTENT_ENCODING).map(String::from);
+        let vary = header(&response, header::VARY).map(String::from);
+        (encoding, vary)
+    }
+
+    #[tokio::test]
+    async fn negotiates_precompressed_variants() {
+        let cases = [
+            (None, None),
+            (Some("identity"), None),
+            (Some("gzip, br"), Some("br")),
+            (Some("gzip"), Some("gzip")),
+            (Some("br;q=0, gzip"), Some("gzip")),
+            (Some("br;q=0, gzip;q=0"), None),
+            (Some("br; q=0.5, gzip;q=1"), Some("br")),
+            (Some("*"), Some("br")),
+            (Some("br;q=0, *"), Some("gzip")),
+            (Some("*;q=0"), None),
+            (Some("GZIP"), Some("gzip")),
+        ];
+        for (accept_encoding, expected) in cases {
+            let (encoding, vary) = negotiate(accept_encoding).await;
+            assert_eq!(encoding.as_deref(), expected, "{:?}", accept_encoding);
+            assert_eq!(
+                vary.as_deref(),
+                Some("Accept-Encoding"),
+                "{:?}",
+                accept_encoding
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn varies_only_when_variants_exist() {
+        let (response, _) =
+            send(Request::get("/robots.txt").header(header::ACCEPT_ENCODING, "br, gzip")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(header(&response, header::CONTENT_ENCODING).is_none());
+        assert!(header(&response, header::VARY).is_none());
+    }
+
+    #[tokio::test]
+    async fn hides_precompressed_siblings() {
+        for path in [
+            "/_app/immutable/app.3f9a1c.js.br",
+            "/_app/immutable/app.3f9a1c.js.gz",
+        ] {
+            let (response, body) = send(Request::get(path)).await;
+            assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", path);
+            assert!(body.is_empty());
+            assert!(response.extensions().get::<StaticAsset>().is_none());
+        }
+    }
+
+    fn range(value: &str) -> axum::http::request::Builder {
+        Request::get("/alphabet.txt").header(header::RANGE, value)
+    }
+
+    #[tokio::test]
+    async fn serves_single_ranges() {
+        let (response, body) = send(range("bytes=-3")).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(
+            header(&response, header::CONTENT_RANGE),
+            Some("bytes 23-25/26")
+        );
+        assert_eq!(header(&response, header::CONTENT_LENGTH), Some("3"));
+        assert_eq!(body, "xyz");
+
+        let (response, _) = send(range("bytes=26-")).await;
+        assert_eq!(response.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(header(&response, header::CONTENT_RANGE), Some("bytes */26"));
+    }
+
+    #[tokio::test]
+    async fn serves_merged_ranges_as_multipart() {
+        let (response, body) = send(range("bytes=20-22, 0-1, 2-2")).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        let content_type = header(&response, header::CONTENT_TYPE).unwrap();
+        let boundary = content_type
+            .strip_prefix("multipart/byteranges; boundary=")
+            .unwrap();
+        assert_eq!(
+            header(&response, header::CONTENT_LENGTH),
+            Some(body.len().to_string().as_str())
+        );
+        assert_eq!(body.matches("Content-Range").count(), 2);
+        assert!(body.contains("Content-Range: bytes 0-2/26\r\n\r\nabc\r\n"));
+        assert!(body.contains("Content-Range: bytes 20-22/26\r\n\r\nuvw\r\n"));
+        assert!(body.ends_with(&format!("--{}--\r\n", boundary)));
+    }
+
+    #[tokio::test]
+    async fn serves_the_whole_file_for_amplifying_ranges() {
+        let (response, body) = send(range("bytes=0-,0-")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(header(&response, header::CONTENT_LENGTH), Some("26"));
+        assert_eq!(body, "abcdefghijklmnopqrstuvwxyz");
+    }
+
+    #[tokio::test]
+    async fn ignores_ranges_for_a_stale_if_range() {
+        let (response, _) = send(Request::get("/alphabet.txt")).await;
+        let etag = header(&response, header::ETAG).unwrap().to_string();
+
+        let (response, body) = send(range("bytes=0-2").header(header::IF_RANGE, &etag)).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(body, "abc");
+
+        let (response, body) = send(range("bytes=0-2").header(header::IF_RANGE, "\"stale\"")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(body.len(), 26);
     }
 }
@@ -0,0 +1,301 @@
+use anyhow::{Context, Result};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+use super::process::FrontendProcess;
+
+/// How often the supervisor checks whether the frontend process is still running
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Lifecycle state of the supervised frontend process
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FrontendState {
+    Starting,
+    Ready,
+    Restarting,
+    /// The restart budget is exhausted and the supervisor gave up
+    Failed,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct FrontendStatus {
+    pub state: FrontendState,
+    /// Number of successful restarts since the server started
+    pub restarts: u32,
+}
+
+/// Controls how the supervisor respawns a crashed frontend process
+#[derive(Debug, Clone, Copy)]
+pub struct RestartPolicy {
+    /// Delay before the first restart attempt
+    pub initial_backoff: Duration,
+    /// Upper bound for the exponentially growing delay
+    pub max_backoff: Duration,
+    /// Maximum number of restart attempts within `budget_window`
+    pub max_restarts: usize,
+    pub budget_window: Duration,
+    /// A process that stays up this long resets the backoff delay
+    pub stable_after: Duration,
+}
+
+impl Default for RestartPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            max_restarts: 5,
+            budget_window: Duration::from_secs(300),
+            stable_after: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Owns the frontend process on a dedicated thread and respawns it when it exits.
+///
+/// The process is spawned from the supervisor thread so that `bind_to_parent`
+/// keeps working across restarts. Dropping the supervisor stops the process.
+/// Once `shutdown` flips to true an exit is expected and nothing is respawned.
+pub struct FrontendSupervisor {
+    stop_tx: mpsc::Sender<()>,
+    thread: Option<JoinHandle<()>>,
+    status: watch::Receiver<FrontendStatus>,
+}
+
+impl FrontendSupervisor {
+    /// Launches the frontend and blocks until the first launch succeeds or fails.
+    pub fn start<F>(
+        launch: F,
+        policy: RestartPolicy,
+        shutdown: watch::Receiver<bool>,
+    ) -> Result<Self>
+    where
+        F: FnMut() -> Result<FrontendProcess> + Send + 'static,
+    {
+        let (status_tx, status) = watch::channel(FrontendStatus {
+            state: FrontendState::Starting,
+            restarts: 0,
+        });
+        let (stop_tx, stop_rx) = mpsc::channel();
+        let (started_tx, started_rx) = mpsc::channel();
+
+        let thread = thread::Builder::new()
+            .name("frontend-supervisor".to_string())
+            .spawn(move || supervise(launch, policy, shutdown, status_tx, stop_rx, started_tx))
+            .context("Failed to spawn frontend supervisor thread")?;
+
+        started_rx
+            .recv()
+            .context("Frontend supervisor exited before the first launch")??;
+
+        Ok(Self {
+            stop_tx,
+            thread: Some(thread),
+            status,
+        })
+    }
+
+    /// Subscribes to lifecycle changes of the supervised process
+    pub fn status(&self) -> watch::Receiver<FrontendStatus> {
+        self.status.clone()
+    }
+}
+
+impl Drop for FrontendSupervisor {
+    fn drop(&mut self) {
+        self.stop_tx.send(()).ok();
+        if let Some(thread) = self.thread.take()
+            && thread.join().is_err()
+        {
+            warn!("Frontend supervisor thread panicked");
+        }
+    }
+}
+
+fn supervise<F>(
+    mut launch: F,
+    policy: RestartPolicy,
+    shutdown: watch::Receiver<bool>,
+    status_tx: watch::Sender<FrontendStatus>,
+    stop_rx: mpsc::Receiver<()>,
+    started_tx: mpsc::Sender<Result<()>>,
+) where
+    F: FnMut() -> Result<FrontendProcess>,
+{
+    let mut process = match launch() {
+        Ok(process) => process,
+        Err(e) => {
+            started_tx.send(Err(e)).ok();
+            return;
+        }
+    };
+    started_tx.send(Ok(())).ok();
+
+    let mut restarts = 0;
+    let mut recent_restarts: VecDeque<Instant> = VecDeque::new();
+    let mut backoff = policy.initial_backoff;
+    let mut started_at = Instant::now();
+    status_tx.send_replace(FrontendStatus {
+        state: FrontendState::Ready,
+        restarts,
+    });
+
+    loop {
+        // Any message or a dropped supervisor handle means shut down
+        if !matches!(stop_rx.recv_timeout(POLL_INTERVAL), Err(RecvTimeoutError::Timeout)) {
+            process.stop();
+            return;
+        }
+
+        let exit_status = match process.try_wait() {
+            Ok(Some(status)) => status,
+            Ok(None) => continue,
+            Err(e) => {
+                warn!(error = %e, "Failed to poll frontend process");
+                continue;
+            }
+        };
+
+        // A Ctrl-C in the terminal also reaches a frontend in our process group
+        if *shutdown.borrow() {
+            info!(exit_status = %exit_status, "Frontend process exited during shutdown");
+            stop_rx.recv().ok();
+            return;
+        }
+
+        let uptime = started_at.elapsed();
+        warn!(
+            exit_status = %exit_status,
+            uptime_secs = uptime.as_secs(),
+            restarts,
+            "Frontend process exited unexpectedly"
+        );
+
+        if uptime >= policy.stable_after {
+            backoff = policy.initial_backoff;
+        }
+
+        loop {
+            while recent_restarts
+                .front()
+                .is_some_and(|at| at.elapsed() > policy.budget_window)
+            {
+                recent_restarts.pop_front();
+            }
+
+            if recent_restarts.len() >= policy.max_restarts {
+                error!(
+                    max_restarts = policy.max_restarts,
+                    window_secs = policy.budget_window.as_secs(),
+                    "Frontend restart budget exhausted, giving up"
+                );
+                status_tx.send_replace(FrontendStatus {
+                    state: FrontendState::Failed,
+                    restarts,
+                });
+                stop_rx.recv().ok();
+                return;
+            }
+
+            status_tx.send_replace(FrontendStatus {
+                state: FrontendState::Restarting,
+                restarts,
+            });
+            info!(
+                delay_ms = backoff.as_millis() as u64,
+                attempt = recent_restarts.len() + 1,
+                "Restarting frontend process"
+            );
+
+            if !matches!(stop_rx.recv_timeout(backoff), Err(RecvTimeoutError::Timeout)) {
+                return;
+            }
+            if *shutdown.borrow() {
+                stop_rx.recv().ok();
+                return;
+            }
+
+            recent_restarts.push_back(Instant::now());
+            backoff = (backoff * 2).min(policy.max_backoff);
+
+            match launch() {
+                Ok(new_process) => {
+                    process = new_process;
+                    started_at = Instant::now();
+                    restarts += 1;
+                    info!(restarts, "Frontend process restarted");
+                    status_tx.send_replace(FrontendStatus {
+                        state: FrontendState::Ready,
+                        restarts,
+                    });
+                    break;
+                }
+                Err(_) if *shutdown.borrow() => {
+                    info!("Frontend restart abandoned, shutdown started");
+                    stop_rx.recv().ok();
+                    return;
+                }
+                Err(e) => {
+                    error!(error = %format!("{:#}", e), "Frontend restart failed");
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Counts launches of a frontend that exits right away
+    fn short_lived(
+        launches: &Arc<AtomicUsize>,
+    ) -> impl FnMut() -> Result<FrontendProcess> + Send + 'static {
+        let launches = launches.clone();
+        move || {
+            launches.fetch_add(1, Ordering::SeqCst);
+            let child = Command::new("sh").args(["-c", "exit 0"]).spawn()?;
+            Ok(FrontendProcess::new(child))
+        }
+    }
+
+    fn quick_policy() -> RestartPolicy {
+        RestartPolicy {
+            initial_backoff: Duration::from_millis(10),
+            ..RestartPolicy::default()
+        }
+    }
+
+    #[test]
+    fn restarts_a_frontend_that_exits() {
+        let launches = Arc::new(AtomicUsize::new(0));
+        let (_shutdown_tx, shutdown) = watch::channel(false);
+        let supervisor =
+            FrontendSupervisor::start(short_lived(&launches), quick_policy(), shutdown).unwrap();
+
+        thread::sleep(POLL_INTERVAL * 4);
+        drop(supervisor);
+        assert!(launches.load(Ordering::SeqCst) > 1);
+    }
+
+    #[test]
+    fn does_not_restart_after_shutdown_started() {
+        let launches = Arc::new(AtomicUsize::new(0));
+        let (shutdown_tx, shutdown) = watch::channel(false);
+        let supervisor =
+            FrontendSupervisor::start(short_lived(&launches), quick_policy(), shutdown).unwrap();
+        shutdown_tx.send_replace(true);
+
+        thread::sleep(POLL_INTERVAL * 4);
+        assert_eq!(launches.load(Ordering::SeqCst), 1);
+        assert_eq!(supervisor.status().borrow().restarts, 0);
+    }
+}
@@ -4,18 +4,18 @@ pub enum Environment {
     Production,
 }
 
-impl Into<&'static str> for Environment {
-    fn into(self) -> &'static str {
-        match self {
+impl From<Environment> for &'static str {
+    fn from(environment: Environment) -> Self {
+        match environment {
             Environment::Development => "development",
             Environment::Production => "production",
         }
     }
 }
 
-impl Into<Environment> for &str {
-    fn into(self) -> Environment {
-        match self {
+impl From<&str> for Environment {
+    fn from(value: &str) -> Self {
+        match value {
             "production" => Environment::Production,
             _ => Environment::Development,
         }
@@ -0,0 +1,314 @@
+use axum::{http::StatusCode, routing::get, Extension, Json, Router};
+use phantom_frame::cache::RefreshTrigger;
+use serde::Serialize;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{broadcast::error::RecvError, watch};
+use tracing::info;
+
+use crate::{
+    embed::{FrontendAddress, FrontendState, FrontendStatus},
+    AppState,
+};
+
+/// Liveness endpoint: the server is running and the frontend has not been given up on
+pub const HEALTHZ_PATH: &str = "/healthz";
+
+/// Readiness endpoint: the frontend is up and accepting connections
+pub const READYZ_PATH: &str = "/readyz";
+
+/// How long the readiness probe waits for the frontend to accept a connection
+const PROBE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// What the health endpoints report on, shared through [`AppState`]
+#[derive(Clone)]
+pub struct Health {
+    started_at: Instant,
+    frontend_status: watch::Receiver<FrontendStatus>,
+    frontend_addr: FrontendAddress,
+    cache: Arc<CacheActivity>,
+}
+
+impl Health {
+    pub fn new(
+        frontend_status: watch::Receiver<FrontendStatus>,
+        frontend_addr: FrontendAddress,
+        refresh_trigger: &RefreshTrigger,
+    ) -> Self {
+        Self {
+            started_at: Instant::now(),
+            frontend_status,
+            frontend_addr,
+            cache: CacheActivity::spawn(refresh_trigger),
+        }
+    }
+
+    pub fn frontend_status(&self) -> FrontendStatus {
+        *self.frontend_status.borrow()
+    }
+}
+
+/// Counts cache refreshes by listening on the same channel the proxy cache does
+#[derive(Default)]
+struct CacheActivity {
+    refreshes: AtomicU64,
+    last_refresh: Mutex<Option<Instant>>,
+}
+
+impl CacheActivity {
+    fn spawn(refresh_trigger: &RefreshTrigger) -> Arc<Self> {
+        let activity = Arc::new(Self::default());
+        let mut refreshes = refresh_trigger.subscribe();
+        let recorder = activity.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let count = match refreshes.recv().await {
+                    Ok(_) => 1,
+                    Err(RecvError::Lagged(missed)) => missed,
+                    Err(RecvError::Closed) => return,
+                };
+                recorder.refreshes.fetch_add(count, Ordering::Relaxed);
+                *recorder.last_refresh.lock().expect("cache activity lock poisoned") =
+                    Some(Instant::now());
+            }
+        });
+
+        activity
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    Ok,
+    Ready,
+    NotReady,
+    Failed,
+}
+
+/// Public body of both probes; it names no internal addresses or settings
+#[derive(Serialize)]
+struct ProbeResponse {
+    status: Status,
+    uptime_secs: u64,
+}
+
+/// Detailed readiness report, served only behind the admin token
+#[derive(Serialize)]
+pub struct StatusReport {
+    status: Status,
+    uptime_secs: u64,
+    frontend: FrontendReport,
+    cache: CacheReport,
+}
+
+#[derive(Serialize)]
+struct FrontendReport {
+    #[serde(flatten)]
+    status: FrontendStatus,
+    address: String,
+    reachable: bool,
+}
+
+#[derive(Serialize)]
+struct CacheReport {
+    key: String,
+    refreshes: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_refresh_secs_ago: Option<u64>,
+}
+
+/// Creates the router for the liveness and readiness endpoints.
+pub fn create_health_router() -> Router {
+    info!("Mounting health endpoints at {} and {}", HEALTHZ_PATH, READYZ_PATH);
+    Router::new()
+        .route(HEALTHZ_PATH, get(liveness))
+        .route(READYZ_PATH, get(readiness))
+}
+
+/// 200 while the server runs; 503 once the supervisor has given up on the frontend,
+/// since only a restart of the whole process can recover from that.
+async fn liveness(
+    Extension(state): Extension<Arc<AppState>>,
+) -> (StatusCode, Json<ProbeResponse>) {
+    let health = &state.health;
+    let (code, status) = match health.frontend_status.borrow().state {
+        FrontendState::Failed => (StatusCode::SERVICE_UNAVAILABLE, Status::Failed),
+        _ => (StatusCode::OK, Status::Ok),
+    };
+
+    (
+        code,
+        Json(ProbeResponse {
+            status,
+            uptime_secs: health.started_at.elapsed().as_secs(),
+        }),
+    )
+}
+
+/// 200 only when the supervisor reports the frontend ready and it accepts connections.
+async fn readiness(
+    Extension(state): Extension<Arc<AppState>>,
+) -> (StatusCode, Json<ProbeResponse>) {
+    let (code, report) = status_report(&state).await;
+
+    (
+        code,
+        Json(ProbeResponse {
+            status: report.status,
+            uptime_secs: report.uptime_secs,
+        }),
+    )
+}
+
+/// The readiness check plus the frontend address, restart count and cache activity,
+/// for the authenticated admin status endpoint.
+pub async fn status(
+    Extension(state): Extension<Arc<AppState>>,
+) -> (StatusCode, Json<StatusReport>) {
+    let (code, report) = status_report(&state).await;
+    (code, Json(report))
+}
+
+async fn status_report(state: &AppState) -> (StatusCode, StatusReport) {
+    let health = &state.health;
+    let frontend_status = health.frontend_status();
+    let reachable = match &health.frontend_addr {
+        FrontendAddress::Tcp { host, port } => matches!(
+            tokio::time::timeout(PROBE_TIMEOUT, tokio::net::TcpStream::connect((host.as_str(), *port)))
+                .await,
+            Ok(Ok(_))
+        ),
+        #[cfg(unix)]
+        FrontendAddress::Unix(path) => matches!(
+            tokio::time::timeout(PROBE_TIMEOUT, tokio::net::UnixStream::connect(path)).await,
+            Ok(Ok(_))
+        ),
+    };
+
+    let (code, status) = match (frontend_status.state, reachable) {
+        (FrontendState::Ready, true) => (StatusCode::OK, Status::Ready),
+        (FrontendState::Failed, _) => (StatusCode::SERVICE_UNAVAILABLE, Status::Failed),
+        _ => (StatusCode::SERVICE_UNAVAILABLE, Status::NotReady),
+    };
+
+    let last_refresh = *health
+        .cache
+        .last_refresh
+        .lock()
+        .expect("cache activity lock poisoned");
+
+    (
+        code,
+        StatusReport {
+            status,
+            uptime_secs: health.started_at.elapsed().as_secs(),
+            frontend: FrontendReport {
+                status: frontend_status,
+                address: health.frontend_addr.to_string(),
+                reachable,
+            },
+            cache: CacheReport {
+                key: state.config.proxy.cache_key.clone(),
+                refreshes: health.cache.refreshes.load(Ordering::Relaxed),
+                last_refresh_secs_ago: last_refresh.map(|at| at.elapsed().as_secs()),
+            },
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    fn app(state: FrontendState, port: u16) -> Router {
+        let refresh_frontend = RefreshTrigger::new();
+        let (_, status) = watch::channel(FrontendStatus { state, restarts: 0 });
+        let frontend_addr = FrontendAddress::Tcp {
+            host: "127.0.0.1".into(),
+            port,
+        };
+        let state = Arc::new(AppState {
+            health: Health::new(status, frontend_addr, &refresh_frontend),
+            refresh_frontend,
+            config: Arc::new(Config::default()),
+            metrics: None,
+        });
+
+        create_health_router().layer(Extension(state))
+    }
+
+    async fn get(app: Router, path: &str) -> (StatusCode, serde_json::Value) {
+        let request = Request::get(path).body(Body::empty()).unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    /// A port nothing listens on
+    async fn closed_port() -> u16 {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        listener.local_addr().unwrap().port()
+    }
+
+    #[tokio::test]
+    async fn liveness_fails_only_once_the_frontend_failed() {
+        let port = closed_port().await;
+        for state in [
+            FrontendState::Starting,
+            FrontendState::Ready,
+            FrontendState::Restarting,
+        ] {
+            let (status, body) = get(app(state, port), HEALTHZ_PATH).await;
+            assert_eq!(status, StatusCode::OK, "{:?}", state);
+            assert_eq!(body["status"], "ok");
+        }
+
+        let (status, body) = get(app(FrontendState::Failed, port), HEALTHZ_PATH).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "failed");
+    }
+
+    #[tokio::test]
+    async fn ready_when_the_frontend_accepts_connections() {
+        let frontend = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = frontend.local_addr().unwrap().port();
+
+        let (status, body) = get(app(FrontendState::Ready, port), READYZ_PATH).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "ready");
+        // Nothing about the frontend's address or the cache leaks publicly
+        let keys: Vec<_> = body.as_object().unwrap().keys().collect();
+        assert_eq!(keys, ["status", "uptime_secs"]);
+    }
+
+    #[tokio::test]
+    async fn not_ready_when_the_probe_fails() {
+        let port = closed_port().await;
+        let (status, body) = get(app(FrontendState::Ready, port), READYZ_PATH).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "not_ready");
+    }
+
+    #[tokio::test]
+    async fn not_ready_until_the_supervisor_reports_ready() {
+        let frontend = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = frontend.local_addr().unwrap().port();
+
+        let (status, body) = get(app(FrontendState::Starting, port), READYZ_PATH).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "not_ready");
+
+        let (status, body) = get(app(FrontendState::Failed, port), READYZ_PATH).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "failed");
+    }
+}
@@ -0,0 +1,360 @@
+use anyhow::{Context, Result};
+use axum::Router;
+use socket2::{Domain, Socket, Type};
+use std::fmt;
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::str::FromStr;
+use tokio_rustls::TlsAcceptor;
+use tracing::info;
+
+use crate::tls::TlsListener;
+
+/// Prefix that marks a listen address as a Unix domain socket path
+const UNIX_PREFIX: &str = "unix:";
+
+/// Connection backlog for TCP listeners
+const BACKLOG: i32 = 1024;
+
+/// One address the server accepts connections on
+///
+/// Written as `127.0.0.1`, `0.0.0.0:8080`, `::`, `[::1]:8080` or `unix:/run/app.sock`.
+/// TCP addresses without a port use `server.port`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ListenAddr {
+    Tcp { ip: IpAddr, port: Option<u16> },
+    Unix(PathBuf),
+}
+
+impl ListenAddr {
+    /// The TCP socket address, using `default_port` when none was given
+    pub fn socket_addr(&self, default_port: u16) -> Option<SocketAddr> {
+        match self {
+            Self::Tcp { ip, port } => Some(SocketAddr::new(*ip, port.unwrap_or(default_port))),
+            Self::Unix(_) => None,
+        }
+    }
+}
+
+impl FromStr for ListenAddr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if let Some(path) = s.strip_prefix(UNIX_PREFIX) {
+            if path.is_empty() {
+                anyhow::bail!("{:?} has no socket path", s);
+            }
+            return Ok(Self::Unix(PathBuf::from(path)));
+        }
+        if let Ok(ip) = s.trim_start_matches('[').trim_end_matches(']').parse() {
+            return Ok(Self::Tcp { ip, port: None });
+        }
+        match s.parse::<SocketAddr>() {
+            Ok(addr) => Ok(Self::Tcp {
+                ip: addr.ip(),
+                port: Some(addr.port()),
+            }),
+            Err(_) => anyhow::bail!(
+                "{:?} is not an IP address, IP:PORT, [IPv6]:PORT or unix:/path",
+                s
+            ),
+        }
+    }
+}
+
+impl TryFrom<String> for ListenAddr {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self> {
+        value.parse()
+    }
+}
+
+impl From<ListenAddr> for String {
+    fn from(addr: ListenAddr) -> Self {
+        addr.to_string()
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp { ip, port: Some(port) } => write!(f, "{}", SocketAddr::new(*ip, *port)),
+            Self::Tcp { ip: IpAddr::V6(ip), port: None } => write!(f, "[{}]", ip),
+            Self::Tcp { ip, port: None } => write!(f, "{}", ip),
+            Self::Unix(path) => write!(f, "{}{}", UNIX_PREFIX, path.display()),
+        }
+    }
+}
+
+/// Options that apply to every listener
+#[derive(Debug, Clone, Copy)]
+pub struct ListenOptions {
+    pub default_port: u16,
+    /// Whether wildcard IPv6 listeners (`::`) refuse IPv4-mapped connections
+    pub ipv6_only: bool,
+    /// Permission bits applied to Unix socket files
+    pub unix_socket_mode: u32,
+}
+
+/// A bound listener, ready to be served
+pub enum BoundListener {
+    Tcp(tokio::net::TcpListener),
+    Tls(TlsListener),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener, UnixSocketFile),
+}
+
+impl BoundListener {
+    /// Binds `addr`, replacing a stale Unix socket file left behind by a previous run.
+    ///
+    /// TCP listeners terminate TLS when an acceptor is given.
+    pub fn bind(
+        addr: &ListenAddr,
+        options: ListenOptions,
+        tls: Option<&TlsAcceptor>,
+    ) -> Result<Self> {
+        match addr {
+            ListenAddr::Tcp { .. } => {
+                let socket_addr = addr
+                    .socket_addr(options.default_port)
+                    .expect("TCP listen address");
+                let listener = bind_tcp(socket_addr, options.ipv6_only)
+                    .with_context(|| format!("Failed to bind {}", socket_addr))?;
+                match tls {
+                    Some(acceptor) => Ok(Self::Tls(TlsListener::new(listener, acceptor.clone())?)),
+                    None => Ok(Self::Tcp(listener)),
+                }
+            }
+            #[cfg(unix)]
+            ListenAddr::Unix(path) => {
+                let (listener, file) = bind_unix(path, options.unix_socket_mode)
+                    .with_context(|| format!("Failed to bind {}", addr))?;
+                Ok(Self::Unix(listener, file))
+            }
+            #[cfg(not(unix))]
+            ListenAddr::Unix(_) => anyhow::bail!("Unix sockets are not supported on this platform"),
+        }
+    }
+
+    /// Human readable address, e.g. `http://[::]:3030` or `unix:/run/app.sock`
+    pub fn describe(&self) -> String {
+        match self {
+            Self::Tcp(listener) => match listener.local_addr() {
+                Ok(addr) => format!("http://{}", addr),
+                Err(_) => "tcp".to_string(),
+            },
+            Self::Tls(listener) => match axum::serve::Listener::local_addr(listener) {
+                Ok(addr) => format!("https://{}", addr),
+                Err(_) => "tls".to_string(),
+            },
+            #[cfg(unix)]
+            Self::Unix(_, file) => format!("{}{}", UNIX_PREFIX, file.path.display()),
+        }
+    }
+
+    /// Serves `app` on this listener until `shutdown` resolves, then drains open connections.
+    pub async fn serve<F>(self, app: Router, shutdown: F) -> std::io::Result<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        match self {
+            Self::Tcp(listener) => {
+                axum::serve(listener, app)
+                    .with_graceful_shutdown(shutdown)
+                    .await
+            }
+            Self::Tls(listener) => {
+                // Handshakes happen on a task of their own, which has to stop along with axum
+                let stop = listener.stopper();
+                let shutdown = async move {
+                    shutdown.await;
+                    stop();
+                };
+                axum::serve(listener, app)
+                    .with_graceful_shutdown(shutdown)
+                    .await
+            }
+            #[cfg(unix)]
+            Self::Unix(listener, _file) => {
+                axum::serve(listener, app)
+                    .with_graceful_shutdown(shutdown)
+                    .await
+            }
+        }
+    }
+}
+
+fn bind_tcp(addr: SocketAddr, ipv6_only: bool) -> std::io::Result<tokio::net::TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+    if addr.is_ipv6() {
+        socket.set_only_v6(ipv6_only)?;
+    }
+    #[cfg(unix)]
+    socket.set_reuse_address(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    socket.listen(BACKLOG)?;
+
+    tokio::net::TcpListener::from_std(socket.into())
+}
+
+/// Removes the socket file once the listener is dropped
+#[cfg(unix)]
+pub struct UnixSocketFile {
+    path: PathBuf,
+}
+
+#[cfg(unix)]
+impl Drop for UnixSocketFile {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.path).ok();
+    }
+}
+
+#[cfg(unix)]
+fn bind_unix(
+    path: &std::path::Path,
+    mode: u32,
+) -> Result<(tokio::net::UnixListener, UnixSocketFile)> {
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            anyhow::bail!("{:?} exists and is not a socket", path);
+        }
+        // A socket nobody answers on is left over from a previous run
+        if std::os::unix::net::UnixStream::connect(path).is_ok() {
+            anyhow::bail!("{:?} is in use by another process", path);
+        }
+        info!("Removing stale socket {:?}", path);
+        std::fs::remove_file(path)?;
+    }
+
+    // Bind in a directory only this user can enter and move the socket into place
+    // once it has its mode, so it is never reachable with the umask's permissions
+    let parent = path
+        .parent()
+        .filter(|parent| !parent.as_os_str().is_empty())
+        .unwrap_or(std::path::Path::new("."));
+    // Short, because socket paths are limited to about 100 bytes
+    let suffix = uuid::Uuid::new_v4().simple().to_string();
+    let staging = parent.join(format!(".sock-{}", &suffix[..8]));
+    std::fs::DirBuilder::new()
+        .mode(0o700)
+        .create(&staging)
+        .with_context(|| format!("Failed to create {:?}", staging))?;
+    let staged = staging.join("socket");
+    let bound = tokio::net::UnixListener::bind(&staged)
+        .context("Failed to bind socket")
+        .and_then(|listener| {
+            std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(mode))
+                .context("Failed to set socket permissions")?;
+            std::fs::rename(&staged, path).context("Failed to move socket into place")?;
+            Ok(listener)
+        });
+    std::fs::remove_file(&staged).ok();
+    std::fs::remove_dir(&staging).ok();
+
+    let file = UnixSocketFile {
+        path: path.to_path_buf(),
+    };
+    Ok((bound?, file))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    const OPTIONS: ListenOptions = ListenOptions {
+        default_port: 0,
+        ipv6_only: false,
+        unix_socket_mode: 0o660,
+    };
+
+    /// Serves a router that answers every request on `listener` in the background
+    fn serve(listener: BoundListener) {
+        let app = Router::new().fallback(|| async { "hello" });
+        tokio::spawn(listener.serve(app, std::future::pending()));
+    }
+
+    fn port(listener: &BoundListener) -> u16 {
+        match listener {
+            BoundListener::Tcp(listener) => listener.local_addr().unwrap().port(),
+            _ => panic!("not a TCP listener"),
+        }
+    }
+
+    async fn get<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S) -> String {
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: test\r\nConnection: close\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn answers_on_ipv4() {
+        let addr = "127.0.0.1".parse().unwrap();
+        let listener = BoundListener::bind(&addr, OPTIONS, None).unwrap();
+        let port = port(&listener);
+        serve(listener);
+
+        let stream = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        assert!(get(stream).await.ends_with("hello"));
+    }
+
+    #[tokio::test]
+    async fn answers_on_ipv6_and_mapped_ipv4_when_dual_stack() {
+        let listener = BoundListener::bind(&"::".parse().unwrap(), OPTIONS, None).unwrap();
+        let port = port(&listener);
+        serve(listener);
+
+        for host in ["::1", "127.0.0.1"] {
+            let stream = TcpStream::connect((host, port)).await.unwrap();
+            assert!(get(stream).await.ends_with("hello"), "via {}", host);
+        }
+    }
+
+    #[tokio::test]
+    async fn ipv6_only_refuses_ipv4() {
+        let options = ListenOptions {
+            ipv6_only: true,
+            ..OPTIONS
+        };
+        let listener = BoundListener::bind(&"::".parse().unwrap(), options, None).unwrap();
+        let port = port(&listener);
+        serve(listener);
+
+        assert!(TcpStream::connect(("127.0.0.1", port)).await.is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn answers_on_a_unix_socket_with_its_mode() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("listen-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir(&dir).unwrap();
+        let path = dir.join("app.sock");
+        let addr = ListenAddr::Unix(path.clone());
+
+        let listener = BoundListener::bind(&addr, OPTIONS, None).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o660);
+        // Only the socket is left behind, not the directory it was bound in
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+        serve(listener);
+
+        let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+        assert!(get(stream).await.ends_with("hello"));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
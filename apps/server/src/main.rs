@@ -1,68 +1,184 @@
 use crate::env::get_enviroment;
+use clap::Parser;
+use std::sync::Arc;
+use tokio::sync::watch;
 use tracing::info;
-use tracing_subscriber;
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
 
+mod admin;
+mod api;
+mod config;
 mod embed;
 mod env;
+mod health;
+mod listen;
+mod metrics;
+mod proxy_events;
+mod request_log;
 mod server;
+mod shutdown;
+#[cfg(feature = "otel")]
+mod telemetry;
+mod tls;
+mod upstream;
 
 #[derive(Clone)]
 pub struct AppState {
     pub refresh_frontend: phantom_frame::cache::RefreshTrigger,
+    pub config: Arc<config::Config>,
+    pub health: health::Health,
+    pub metrics: Option<Arc<metrics::Metrics>>,
 }
 
-fn find_available_port() -> std::io::Result<u16> {
-    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
-    let port = listener.local_addr()?.port();
-    Ok(port)
+/// Level filter from `RUST_LOG`, defaulting to info
+fn log_filter() -> tracing_subscriber::EnvFilter {
+    tracing_subscriber::EnvFilter::try_from_default_env()
+        .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"))
 }
 
 #[tokio::main]
 async fn main() {
     dotenv::dotenv().ok();
-    
-    // Initialize tracing subscriber with fallback
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"))
-        )
-        .init();
-    
-    let environment = get_enviroment();
-    info!("Starting server in {:?} mode", environment);
 
-    let frontend_port = match environment {
-        env::Environment::Development => 5173,
-        env::Environment::Production => {
-            find_available_port().expect("Failed to find available port for frontend")
+    let cli = config::Cli::parse();
+    let config = match config::Config::load(&cli) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid configuration: {:#}", e);
+            std::process::exit(2);
         }
     };
 
-    let port = std::env::var("PORT")
-        .ok()
-        .and_then(|p| p.parse::<u16>().ok())
-        .unwrap_or(3030);
-    
-    info!("Frontend port: {}", frontend_port);
-    info!("Server port: {}", port);
+    if cli.print_config {
+        match config.to_toml() {
+            Ok(toml) => print!("{}", toml),
+            Err(e) => {
+                eprintln!("{:#}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
 
-    #[cfg(debug_assertions)]
-    let _dev_server = embed::DevServer::start().expect("Failed to start dev server");
+    let metrics = config.metrics.enabled.then(|| {
+        let api_prefix = &config.server.api_prefix;
+        let exclude_rules = server::cache_exclude_rules(&config.proxy, api_prefix);
+        Arc::new(metrics::Metrics::new(exclude_rules, api_prefix))
+    });
+
+    #[cfg(feature = "otel")]
+    let telemetry = match telemetry::Telemetry::init(&config.telemetry) {
+        Ok(telemetry) => telemetry,
+        Err(e) => {
+            eprintln!("Failed to set up tracing export: {:#}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Initialize tracing subscriber with fallback; cache outcomes are read from proxy events
+    let log_layer = match config.log.format {
+        config::LogFormat::Text => tracing_subscriber::fmt::layer().boxed(),
+        config::LogFormat::Json => tracing_subscriber::fmt::layer()
+            .json()
+            .with_current_span(true)
+            .with_span_list(false)
+            .boxed(),
+    };
+    let subscriber = tracing_subscriber::registry()
+        .with(log_layer.with_filter(log_filter()))
+        .with(proxy_events::ProxyEventLayer.with_filter(proxy_events::ProxyEventLayer::filter()));
+    #[cfg(feature = "otel")]
+    let subscriber = subscriber.with(
+        telemetry
+            .as_ref()
+            .map(|telemetry| telemetry.layer().with_filter(log_filter())),
+    );
+    subscriber.init();
+
+    let environment = get_enviroment();
+    info!("Starting server in {:?} mode", environment);
+
+    info!("Shutdown timeout: {:?}", config.server.shutdown_timeout());
 
     #[cfg(not(debug_assertions))]
-    {
-        embed::run_frontend(frontend_port).expect("Failed to start frontend");
-    }
+    let mime_types = embed::MimeTypes::new(config.assets.mime_types.clone());
+
+    // Flips to true on SIGINT/SIGTERM; from then on the supervisor lets the frontend exit.
+    // Listening starts before the frontend is launched, so a signal during startup stops it too
+    let (shutdown_tx, shutdown_rx) = watch::channel(false);
+    shutdown::listen(shutdown_tx.clone()).expect("Failed to install signal handlers");
+
+    let launcher = embed::create_launcher(&config.frontend)
+        .expect("Failed to prepare frontend")
+        .with_shutdown(shutdown_rx.clone());
+
+    // Waiting for the first launch blocks, so keep it off the async workers
+    let started = {
+        let launcher = launcher.clone();
+        let shutdown_rx = shutdown_rx.clone();
+        tokio::task::spawn_blocking(move || {
+            embed::FrontendSupervisor::start(
+                move || launcher.launch(),
+                embed::RestartPolicy::default(),
+                shutdown_rx,
+            )
+        })
+        .await
+        .expect("Frontend supervisor panicked")
+    };
+    let frontend = match started {
+        Ok(frontend) => frontend,
+        Err(_) if *shutdown_rx.borrow() => {
+            info!("Shutdown complete before the frontend was ready");
+            return;
+        }
+        Err(e) => panic!("Failed to start frontend: {:?}", e),
+    };
+
+    // The address is settled by the first launch, which start() waits for
+    let upstream = launcher.upstream().expect("Frontend has no upstream");
+    let frontend_addr = launcher.ready_address().expect("Invalid frontend address");
+    info!("Frontend address: {}", frontend_addr);
+
+    let config = Arc::new(config);
 
     #[cfg(not(debug_assertions))]
-    let result = server::start_server(port, frontend_port, environment, embed::AssetsLayer).await;
+    let result = server::start_server(
+        config,
+        &upstream,
+        frontend_addr,
+        environment,
+        frontend.status(),
+        metrics,
+        embed::AssetsLayer::new(mime_types),
+        shutdown_tx,
+    )
+    .await;
 
     #[cfg(debug_assertions)]
-    let result = server::start_server(port, frontend_port, environment).await;
+    let result = server::start_server(
+        config,
+        &upstream,
+        frontend_addr,
+        environment,
+        frontend.status(),
+        metrics,
+        shutdown_tx,
+    )
+    .await;
+
+    // Stop the frontend only after in-flight requests have drained; this waits out its grace period
+    tokio::task::spawn_blocking(move || drop(frontend)).await.ok();
+
+    #[cfg(feature = "otel")]
+    if let Some(telemetry) = telemetry {
+        telemetry.shutdown().await;
+    }
 
     if let Err(e) = result {
-        tracing::error!("Server error: {}", e);
+        tracing::error!("Server error: {:#}", e);
         std::process::exit(1);
     }
-}
\ No newline at end of file
+
+    info!("Shutdown complete");
+}
@@ -0,0 +1,431 @@
+use axum::{
+    body::Body,
+    extract::MatchedPath,
+    http::{header, Method, Request},
+    response::{IntoResponse, Response},
+    routing::get,
+    Extension, Router,
+};
+use phantom_frame::path_matcher::matches_pattern_with_method;
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tower::{Layer, Service};
+use tracing::info;
+
+use crate::{
+    embed::FrontendState,
+    proxy_events::{self, CacheOutcome, ProxyOutcome},
+    AppState,
+};
+
+/// Upper bounds of the request latency histogram, in seconds
+const LATENCY_BUCKETS: [f64; 11] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Route label for requests answered by the proxy fallback
+const PROXY_ROUTE: &str = "proxy";
+
+/// Route label for requests answered by the embedded static assets
+const STATIC_ROUTE: &str = "static";
+
+/// Methods labelled by name; any other method is counted as `OTHER`, since
+/// clients can send arbitrary ones and each would add a series
+const LABELLED_METHODS: [Method; 9] = [
+    Method::GET,
+    Method::HEAD,
+    Method::POST,
+    Method::PUT,
+    Method::DELETE,
+    Method::PATCH,
+    Method::OPTIONS,
+    Method::CONNECT,
+    Method::TRACE,
+];
+
+/// Process-wide metrics, rendered in the Prometheus text format
+pub struct Metrics {
+    /// Exclude rules the proxy was configured with, used to label cache bypasses
+    exclude_rules: Vec<String>,
+    /// Route label for API requests no handler matched, e.g. `/api/*`
+    api_fallback_route: String,
+    api_prefix: String,
+    requests: Mutex<BTreeMap<RequestLabels, Histogram>>,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+    cache_bypasses: Mutex<BTreeMap<String, u64>>,
+    upstream_errors: AtomicU64,
+    asset_bytes: Mutex<BTreeMap<String, u64>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct RequestLabels {
+    route: String,
+    method: String,
+    status: u16,
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if value <= bound {
+                *bucket += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+impl Metrics {
+    pub fn new(exclude_rules: Vec<String>, api_prefix: &str) -> Self {
+        Self {
+            exclude_rules,
+            api_fallback_route: format!("{}/*", api_prefix),
+            api_prefix: format!("{}/", api_prefix),
+            requests: Mutex::default(),
+            cache_hits: AtomicU64::new(0),
+            cache_misses: AtomicU64::new(0),
+            cache_bypasses: Mutex::default(),
+            upstream_errors: AtomicU64::new(0),
+            asset_bytes: Mutex::default(),
+        }
+    }
+
+    fn observe_request(&self, labels: RequestLabels, seconds: f64) {
+        self.requests
+            .lock()
+            .expect("metrics lock poisoned")
+            .entry(labels)
+            .or_default()
+            .observe(seconds);
+    }
+
+    fn observe_asset(&self, encoding: &str, bytes: u64) {
+        *self
+            .asset_bytes
+            .lock()
+            .expect("metrics lock poisoned")
+            .entry(encoding.to_string())
+            .or_default() += bytes;
+    }
+
+    fn observe_proxy(&self, method: &str, path: &str, outcome: ProxyOutcome) {
+        match outcome.cache {
+            Some(CacheOutcome::Hit) => {
+                self.cache_hits.fetch_add(1, Ordering::Relaxed);
+            }
+            Some(CacheOutcome::Miss) => {
+                self.cache_misses.fetch_add(1, Ordering::Relaxed);
+            }
+            Some(CacheOutcome::Bypass) => self.observe_bypass(method, path),
+            None => {}
+        }
+        if outcome.upstream_error {
+            self.upstream_errors.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn observe_bypass(&self, method: &str, path: &str) {
+        let rule = self
+            .exclude_rules
+            .iter()
+            .find(|rule| matches_pattern_with_method(Some(method), path, rule))
+            .map_or("other", String::as_str);
+
+        *self
+            .cache_bypasses
+            .lock()
+            .expect("metrics lock poisoned")
+            .entry(rule.to_string())
+            .or_default() += 1;
+    }
+
+    /// Renders every metric; frontend metrics are read from the supervisor at scrape time.
+    fn render(&self, state: &AppState) -> Result<String, fmt::Error> {
+        let mut out = String::new();
+
+        writeln!(out, "# HELP http_requests_total Requests handled, by method, route and status.")?;
+        writeln!(out, "# TYPE http_requests_total counter")?;
+        let requests = self.requests.lock().expect("metrics lock poisoned");
+        for (labels, histogram) in requests.iter() {
+            writeln!(out, "http_requests_total{{{}}} {}", labels, histogram.count)?;
+        }
+
+        writeln!(out, "# HELP http_request_duration_seconds Time until the response head was ready.")?;
+        writeln!(out, "# TYPE http_request_duration_seconds histogram")?;
+        for (labels, histogram) in requests.iter() {
+            for (bound, count) in LATENCY_BUCKETS.iter().zip(histogram.buckets) {
+                writeln!(
+                    out,
+                    "http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, bound, count
+                )?;
+            }
+            writeln!(
+                out,
+                "http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, histogram.count
+            )?;
+            writeln!(out, "http_request_duration_seconds_sum{{{}}} {}", labels, histogram.sum)?;
+            writeln!(out, "http_request_duration_seconds_count{{{}}} {}", labels, histogram.count)?;
+        }
+        drop(requests);
+
+        writeln!(out, "# HELP proxy_cache_requests_total Proxied requests by cache outcome.")?;
+        writeln!(out, "# TYPE proxy_cache_requests_total counter")?;
+        let hits = self.cache_hits.load(Ordering::Relaxed);
+        let misses = self.cache_misses.load(Ordering::Relaxed);
+        writeln!(out, "proxy_cache_requests_total{{result=\"hit\"}} {}", hits)?;
+        writeln!(out, "proxy_cache_requests_total{{result=\"miss\"}} {}", misses)?;
+        let bypasses = self.cache_bypasses.lock().expect("metrics lock poisoned");
+        writeln!(
+            out,
+            "proxy_cache_requests_total{{result=\"bypass\"}} {}",
+            bypasses.values().sum::<u64>()
+        )?;
+
+        writeln!(out, "# HELP proxy_cache_bypass_total Proxied requests not cached, by the exclude rule that matched.")?;
+        writeln!(out, "# TYPE proxy_cache_bypass_total counter")?;
+        for (rule, count) in bypasses.iter() {
+            writeln!(out, "proxy_cache_bypass_total{{rule=\"{}\"}} {}", Escaped(rule), count)?;
+        }
+        drop(bypasses);
+
+        writeln!(out, "# HELP proxy_upstream_errors_total Failed requests from the proxy to the frontend.")?;
+        writeln!(out, "# TYPE proxy_upstream_errors_total counter")?;
+        writeln!(
+            out,
+            "proxy_upstream_errors_total {}",
+            self.upstream_errors.load(Ordering::Relaxed)
+        )?;
+
+        let frontend = state.health.frontend_status();
+        writeln!(out, "# HELP frontend_restarts_total Times the supervisor restarted the frontend process.")?;
+        writeln!(out, "# TYPE frontend_restarts_total counter")?;
+        writeln!(out, "frontend_restarts_total {}", frontend.restarts)?;
+        writeln!(out, "# HELP frontend_state Current lifecycle state of the frontend process.")?;
+        writeln!(out, "# TYPE frontend_state gauge")?;
+        for (name, state) in [
+            ("starting", FrontendState::Starting),
+            ("ready", FrontendState::Ready),
+            ("restarting", FrontendState::Restarting),
+            ("failed", FrontendState::Failed),
+        ] {
+            let value = u8::from(frontend.state == state);
+            writeln!(out, "frontend_state{{state=\"{}\"}} {}", name, value)?;
+        }
+
+        writeln!(out, "# HELP static_asset_bytes_total Body bytes of embedded static assets served, by content encoding.")?;
+        writeln!(out, "# TYPE static_asset_bytes_total counter")?;
+        for (encoding, bytes) in self.asset_bytes.lock().expect("metrics lock poisoned").iter() {
+            writeln!(out, "static_asset_bytes_total{{encoding=\"{}\"}} {}", Escaped(encoding), bytes)?;
+        }
+
+        Ok(out)
+    }
+}
+
+impl fmt::Display for RequestLabels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "method=\"{}\",route=\"{}\",status=\"{}\"",
+            Escaped(&self.method),
+            Escaped(&self.route),
+            self.status
+        )
+    }
+}
+
+/// Escapes a Prometheus label value
+struct Escaped<'a>(&'a str);
+
+impl fmt::Display for Escaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '\\' => f.write_str("\\\\")?,
+                '"' => f.write_str("\\\"")?,
+                '\n' => f.write_str("\\n")?,
+                c => f.write_char(c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Creates the router serving the metrics at `path`.
+pub fn create_metrics_router(path: &str) -> Router {
+    info!("Mounting metrics endpoint at {}", path);
+    Router::new().route(path, get(render_metrics))
+}
+
+async fn render_metrics(Extension(state): Extension<Arc<AppState>>) -> Response {
+    let Some(metrics) = &state.metrics else {
+        return axum::http::StatusCode::NOT_FOUND.into_response();
+    };
+
+    match metrics.render(&state) {
+        Ok(body) => (
+            [(header::CONTENT_TYPE, "text/plain; version=0.0.4; charset=utf-8")],
+            body,
+        )
+            .into_response(),
+        Err(_) => axum::http::StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Records request counts, latency and static asset bytes for every response.
+#[derive(Clone)]
+pub struct MetricsLayer {
+    metrics: Arc<Metrics>,
+}
+
+impl MetricsLayer {
+    pub fn new(metrics: Arc<Metrics>) -> Self {
+        Self { metrics }
+    }
+}
+
+impl<S> Layer<S> for MetricsLayer {
+    type Service = MetricsMiddleware<S>;
+
+    fn layer(&self, inner: S) -> MetricsMiddleware<S> {
+        MetricsMiddleware {
+            inner,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct MetricsMiddleware<S> {
+    inner: S,
+    metrics: Arc<Metrics>,
+}
+
+impl<S> Service<Request<Body>> for MetricsMiddleware<S>
+where
+    S: Service<Request<Body>, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let start = Instant::now();
+        let method = req.method().clone();
+        let matched = req
+            .extensions()
+            .get::<MatchedPath>()
+            .map(|path| path.as_str().to_string());
+        let path = req.uri().path().to_string();
+        let is_api = path.starts_with(&self.metrics.api_prefix);
+        let metrics = self.metrics.clone();
+        let future = self.inner.call(req);
+
+        Box::pin(proxy_events::track(async move {
+            let response = future.await?;
+            metrics.observe_proxy(method.as_str(), &path, proxy_events::current());
+
+            let is_asset = is_static_asset(&response);
+            if is_asset && method != Method::HEAD {
+                let encoding = response
+                    .headers()
+                    .get(header::CONTENT_ENCODING)
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or("identity");
+                let bytes = response
+                    .headers()
+                    .get(header::CONTENT_LENGTH)
+                    .and_then(|v| v.to_str().ok())
+                    .and_then(|v| v.parse().ok())
+                    .unwrap_or(0);
+                metrics.observe_asset(encoding, bytes);
+            }
+
+            let route = match matched {
+                Some(path) => path,
+                None if is_asset => STATIC_ROUTE.to_string(),
+                None if is_api => metrics.api_fallback_route.clone(),
+                None => PROXY_ROUTE.to_string(),
+            };
+            let labels = RequestLabels {
+                route,
+                method: method_label(&method),
+                status: response.status().as_u16(),
+            };
+            metrics.observe_request(labels, start.elapsed().as_secs_f64());
+
+            Ok(response)
+        }))
+    }
+}
+
+fn method_label(method: &Method) -> String {
+    if LABELLED_METHODS.contains(method) {
+        method.to_string()
+    } else {
+        "OTHER".to_string()
+    }
+}
+
+#[cfg(not(debug_assertions))]
+fn is_static_asset(response: &Response) -> bool {
+    response
+        .extensions()
+        .get::<crate::embed::StaticAsset>()
+        .is_some()
+}
+
+#[cfg(debug_assertions)]
+fn is_static_asset(_response: &Response) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn labels_unknown_methods_as_other() {
+        let metrics = Arc::new(Metrics::new(Vec::new(), "/api"));
+        let app = Router::new()
+            .fallback(|| async { "ok" })
+            .layer(MetricsLayer::new(metrics.clone()));
+
+        for method in ["GET", "PURGE", "X-RANDOM-1", "X-RANDOM-2"] {
+            let request = Request::builder()
+                .method(method)
+                .uri("/")
+                .body(Body::empty())
+                .unwrap();
+            app.clone().oneshot(request).await.unwrap();
+        }
+
+        let requests = metrics.requests.lock().unwrap();
+        let methods: Vec<(&str, u64)> = requests
+            .iter()
+            .map(|(labels, histogram)| (labels.method.as_str(), histogram.count))
+            .collect();
+        assert_eq!(methods, [("GET", 1), ("OTHER", 3)]);
+    }
+}
@@ -0,0 +1,213 @@
+use std::cell::Cell;
+use std::fmt;
+use std::future::Future;
+use tracing::{field::Field, Event, Level, Subscriber};
+use tracing_subscriber::{filter::Targets, layer};
+
+/// Tracing target of phantom-frame's request handler, whose events report cache outcomes
+const PROXY_TARGET: &str = "phantom_frame::proxy";
+
+/// How the proxy cache handled a request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CacheOutcome {
+    Hit,
+    Miss,
+    /// Excluded from caching and proxied directly
+    Bypass,
+}
+
+impl CacheOutcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CacheOutcome::Hit => "hit",
+            CacheOutcome::Miss => "miss",
+            CacheOutcome::Bypass => "bypass",
+        }
+    }
+}
+
+/// What the proxy reported while handling the current request
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProxyOutcome {
+    /// `None` when the request never reached the proxy
+    pub cache: Option<CacheOutcome>,
+    pub upstream_error: bool,
+}
+
+tokio::task_local! {
+    static OUTCOME: Cell<ProxyOutcome>;
+}
+
+/// Runs a request future so that [`current`] reports what the proxy did for it.
+///
+/// Nested calls share the outermost scope, so every layer sees the same outcome.
+pub async fn track<F: Future>(future: F) -> F::Output {
+    if OUTCOME.try_with(|_| ()).is_ok() {
+        future.await
+    } else {
+        OUTCOME.scope(Cell::default(), future).await
+    }
+}
+
+/// The outcome recorded so far for the request being tracked, if any
+pub fn current() -> ProxyOutcome {
+    OUTCOME.try_with(Cell::get).unwrap_or_default()
+}
+
+/// Updates the outcome of the request being tracked; does nothing outside [`track`]
+pub fn record(apply: impl FnOnce(&mut ProxyOutcome)) {
+    OUTCOME
+        .try_with(|outcome| {
+            let mut current = outcome.get();
+            apply(&mut current);
+            outcome.set(current);
+        })
+        .ok();
+}
+
+/// Reads cache outcomes from phantom-frame's request handler events.
+///
+/// phantom-frame has no hooks for cache hits and misses, but it logs each outcome
+/// at debug level from inside the request future. Register this layer with
+/// [`ProxyEventLayer::filter`] so those events reach it regardless of the log filter.
+pub struct ProxyEventLayer;
+
+impl ProxyEventLayer {
+    /// Lets only phantom-frame's handler events through
+    pub fn filter() -> Targets {
+        Targets::new().with_target(PROXY_TARGET, Level::DEBUG)
+    }
+}
+
+impl<S: Subscriber> tracing_subscriber::Layer<S> for ProxyEventLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: layer::Context<'_, S>) {
+        let mut message = MessageVisitor(String::new());
+        event.record(&mut message);
+        let message = message.0;
+
+        if *event.metadata().level() == Level::ERROR {
+            // Everything but a broken client body is a failure talking to the frontend
+            if !message.starts_with("Failed to read request body") {
+                record(|outcome| outcome.upstream_error = true);
+            }
+        } else if message.starts_with("Cache hit for:") || message.starts_with("404 cache hit for:") {
+            record(|outcome| outcome.cache = Some(CacheOutcome::Hit));
+        } else if message.starts_with("Cache miss for:") {
+            record(|outcome| outcome.cache = Some(CacheOutcome::Miss));
+        } else if message.ends_with("not cacheable (filtered), proxying directly") {
+            record(|outcome| outcome.cache = Some(CacheOutcome::Bypass));
+        }
+    }
+}
+
+struct MessageVisitor(String);
+
+impl tracing::field::Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.0 = format!("{:?}", value);
+        }
+    }
+}
+
+// These depend on phantom-frame's log messages, which is why Cargo.toml pins its version
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request, Router};
+    use phantom_frame::CreateProxyConfig;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use tower::ServiceExt;
+    use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
+
+    fn capture() -> tracing::subscriber::DefaultGuard {
+        tracing_subscriber::registry()
+            .with(ProxyEventLayer.with_filter(ProxyEventLayer::filter()))
+            .set_default()
+    }
+
+    /// A frontend that answers every request with 200
+    fn frontend() -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok"
+                )
+                .unwrap();
+            }
+        });
+        url
+    }
+
+    async fn outcome(proxy: &Router, method: &str, path: &str) -> ProxyOutcome {
+        let request = Request::builder()
+            .method(method)
+            .uri(path)
+            .body(Body::empty())
+            .unwrap();
+        track(async {
+            proxy.clone().oneshot(request).await.unwrap();
+            current()
+        })
+        .await
+    }
+
+    #[tokio::test]
+    async fn reads_cache_outcomes_from_the_proxy() {
+        let _guard = capture();
+        let config =
+            CreateProxyConfig::new(frontend()).with_exclude_paths(vec!["POST *".to_string()]);
+        let (proxy, _refresh) = phantom_frame::create_proxy(config);
+
+        let miss = outcome(&proxy, "GET", "/page").await;
+        assert_eq!(miss.cache, Some(CacheOutcome::Miss));
+        assert!(!miss.upstream_error);
+        let hit = outcome(&proxy, "GET", "/page").await;
+        assert_eq!(hit.cache, Some(CacheOutcome::Hit));
+        let bypass = outcome(&proxy, "POST", "/form").await;
+        assert_eq!(bypass.cache, Some(CacheOutcome::Bypass));
+    }
+
+    #[tokio::test]
+    async fn flags_failures_to_reach_the_frontend() {
+        let _guard = capture();
+        // Nothing listens on a port that was just released
+        let port = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config = CreateProxyConfig::new(format!("http://127.0.0.1:{}", port));
+        let (proxy, _refresh) = phantom_frame::create_proxy(config);
+
+        let failed = outcome(&proxy, "GET", "/page").await;
+        assert_eq!(failed.cache, Some(CacheOutcome::Miss));
+        assert!(failed.upstream_error);
+    }
+
+    #[tokio::test]
+    async fn ignores_events_outside_the_proxy_and_outside_a_request() {
+        let _guard = capture();
+
+        tracing::error!(target: PROXY_TARGET, "Failed to fetch from backend: refused");
+        let outcome = track(async {
+            tracing::error!(target: PROXY_TARGET, "Failed to read request body: reset");
+            tracing::debug!(target: "other", "Cache hit for: GET /page");
+            current()
+        })
+        .await;
+
+        assert_eq!(outcome.cache, None);
+        assert!(!outcome.upstream_error);
+    }
+}
@@ -0,0 +1,112 @@
+use axum::{
+    body::{Body, HttpBody},
+    http::{header::HeaderName, HeaderValue, Request},
+    response::Response,
+};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+use tower::{Layer, Service};
+use tracing::{field::Empty, info, info_span, warn, Instrument};
+
+use crate::proxy_events::{self, CacheOutcome};
+
+/// Correlation header accepted from clients, forwarded to the frontend and echoed in responses
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longer incoming IDs are replaced rather than trusted
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// Assigns each request an ID and logs one line per request once the response is ready.
+///
+/// An incoming `X-Request-Id` is kept if it looks sane, otherwise a UUID is generated.
+/// The ID is set on the request before it reaches the proxy, so the SvelteKit
+/// upstream receives it too, and every event logged while handling the request
+/// carries it through the `request` span.
+#[derive(Clone, Default)]
+pub struct RequestLogLayer;
+
+impl<S> Layer<S> for RequestLogLayer {
+    type Service = RequestLogMiddleware<S>;
+
+    fn layer(&self, inner: S) -> RequestLogMiddleware<S> {
+        RequestLogMiddleware { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct RequestLogMiddleware<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for RequestLogMiddleware<S>
+where
+    S: Service<Request<Body>, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        let start = Instant::now();
+        let request_id = req
+            .headers()
+            .get(&REQUEST_ID_HEADER)
+            .filter(|id| is_valid_request_id(id))
+            .cloned()
+            .unwrap_or_else(generate_request_id);
+        req.headers_mut().insert(REQUEST_ID_HEADER, request_id.clone());
+
+        let span = info_span!(
+            "request",
+            request_id = request_id.to_str().unwrap_or_default(),
+            method = %req.method(),
+            path = req.uri().path(),
+            otel.name = Empty,
+            otel.kind = Empty,
+        );
+        #[cfg(feature = "otel")]
+        crate::telemetry::continue_trace(&span, &req);
+        let future = span.in_scope(|| self.inner.call(req));
+
+        Box::pin(proxy_events::track(
+            async move {
+                let mut response = future.await?;
+                response.headers_mut().insert(REQUEST_ID_HEADER, request_id);
+
+                let status = response.status().as_u16();
+                let latency_ms = start.elapsed().as_secs_f64() * 1000.0;
+                let cache = proxy_events::current().cache.map(CacheOutcome::as_str);
+                let bytes = response.body().size_hint().exact();
+
+                if response.status().is_server_error() {
+                    warn!(status, latency_ms, cache, bytes, "Request failed");
+                } else {
+                    info!(status, latency_ms, cache, bytes, "Request completed");
+                }
+
+                Ok(response)
+            }
+            .instrument(span),
+        ))
+    }
+}
+
+/// Accepts printable ASCII without spaces, so IDs are safe to log and forward
+fn is_valid_request_id(id: &HeaderValue) -> bool {
+    let bytes = id.as_bytes();
+    !bytes.is_empty()
+        && bytes.len() <= MAX_REQUEST_ID_LEN
+        && bytes.iter().all(|b| b.is_ascii_graphic())
+}
+
+fn generate_request_id() -> HeaderValue {
+    let id = uuid::Uuid::new_v4().to_string();
+    HeaderValue::from_str(&id).expect("UUIDs are valid header values")
+}
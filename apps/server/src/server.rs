@@ -1,77 +1,361 @@
-use anyhow::Result;
+use anyhow::{Context, Result};
 use axum::{Extension, Router};
-use phantom_frame::CreateProxyConfig;
+use phantom_frame::{CreateProxyConfig, cache::RefreshTrigger};
 use std::sync::Arc;
-use tracing::{info, instrument};
+use tokio::{sync::watch, task::JoinSet};
+use tracing::{info, instrument, warn};
 
-use crate::{env::Environment, AppState};
+use crate::{
+    AppState, admin, api,
+    config::{Config, ProxyConfig},
+    embed::{FrontendAddress, FrontendState, FrontendStatus},
+    env::Environment,
+    health::{self, Health},
+    listen::BoundListener,
+    metrics::{self, Metrics, MetricsLayer},
+    request_log::RequestLogLayer,
+    tls,
+    upstream::Upstream,
+};
 
-#[instrument(skip_all, fields(port = %port, frontend_port = %frontend_port))]
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip_all, fields(upstream = %upstream))]
 pub async fn start_server(
-    port: u16,
-    frontend_port: u16,
+    config: Arc<Config>,
+    upstream: &Upstream,
+    frontend_addr: FrontendAddress,
     environment: Environment,
+    frontend_status: watch::Receiver<FrontendStatus>,
+    metrics: Option<Arc<Metrics>>,
     #[cfg(not(debug_assertions))] assets_layer: crate::embed::AssetsLayer,
+    shutdown_tx: watch::Sender<bool>,
 ) -> Result<()> {
     info!("Initializing server");
+    let (app, _state) = create_app(
+        &config,
+        upstream,
+        frontend_addr,
+        environment,
+        frontend_status,
+        metrics,
+        #[cfg(not(debug_assertions))]
+        assets_layer,
+    )
+    .await?;
+
+    // Bind every listener before serving so a bad address fails startup as a whole
+    let listen_options = config.server.listen_options();
+    let tls_acceptor = tls::acceptor(&config.tls)?;
+    let listeners = config
+        .server
+        .listen
+        .iter()
+        .map(|addr| BoundListener::bind(addr, listen_options, tls_acceptor.as_ref()))
+        .collect::<Result<Vec<_>>>()?;
+
+    let shutdown_timeout = config.server.shutdown_timeout();
+    let shutdown_rx = shutdown_tx.subscribe();
+
+    let mut servers = JoinSet::new();
+    for listener in listeners {
+        info!("Server running on {}", listener.describe());
+        let mut shutdown_rx = shutdown_rx.clone();
+        let signal = async move {
+            shutdown_rx.wait_for(|started| *started).await.ok();
+        };
+        servers.spawn(listener.serve(app.clone(), signal));
+    }
+
+    // Drain in-flight requests, but give up on them once the deadline has passed
+    let mut shutdown_started = shutdown_rx.clone();
+    let result = tokio::select! {
+        result = join_servers(&mut servers, &shutdown_tx) => result,
+        _ = async {
+            shutdown_started.wait_for(|started| *started).await.ok();
+            tokio::time::sleep(shutdown_timeout).await;
+        } => {
+            warn!("Connections still open after {:?}, forcing shutdown", shutdown_timeout);
+            Ok(())
+        }
+    };
+    servers.abort_all();
+    result?;
+
+    info!("Server stopped");
+    Ok(())
+}
+
+/// The router every listener serves and the state it shares with its handlers
+async fn create_app(
+    config: &Arc<Config>,
+    upstream: &Upstream,
+    frontend_addr: FrontendAddress,
+    environment: Environment,
+    frontend_status: watch::Receiver<FrontendStatus>,
+    metrics: Option<Arc<Metrics>>,
+    #[cfg(not(debug_assertions))] assets_layer: crate::embed::AssetsLayer,
+) -> Result<(Router, Arc<AppState>)> {
+    let api_prefix = config.server.api_prefix.clone();
+
+    // Create the proxy once so the refresh trigger in the state clears the cache that serves traffic
+    let (proxy_router, refresh_frontend) =
+        create_proxy_router(upstream, environment, &config.proxy, &api_prefix).await?;
+
+    let health = Health::new(frontend_status.clone(), frontend_addr, &refresh_frontend);
+    spawn_refresh_on_restart(frontend_status, refresh_frontend.clone());
+
     // Create application state
-    let state = Arc::new(create_app_state(frontend_port, environment).await?);
+    let state = Arc::new(AppState {
+        refresh_frontend,
+        config: config.clone(),
+        health,
+        metrics: metrics.clone(),
+    });
+
+    // Rust routes are merged before the proxy so they take precedence over its fallback
+    let mut router = Router::new()
+        .merge(health::create_health_router())
+        .merge(api::create_api_router(&api_prefix));
+    if let Some(admin_router) = admin::create_admin_router() {
+        router = router.merge(admin_router);
+    }
+    if metrics.is_some() {
+        router = router.merge(metrics::create_metrics_router(&config.metrics.path));
+    }
 
     // Create Axum router with proxy
     #[cfg(not(debug_assertions))]
-    let app = Router::new()
-        .merge(create_proxy_router(frontend_port, environment).await?)
+    let app = router
+        .merge(proxy_router)
         .layer(assets_layer)
-        .layer(Extension(state));
+        .layer(Extension(state.clone()));
 
     #[cfg(debug_assertions)]
-    let app = Router::new()
-        .merge(create_proxy_router(frontend_port, environment).await?)
-        .layer(Extension(state));
+    let app = router.merge(proxy_router).layer(Extension(state.clone()));
 
-    // Start server
-    let listener = tokio::net::TcpListener::bind(format!("127.0.0.1:{}", port))
-        .await?;
+    // Outermost, so that static assets are measured and logged too
+    let app = match metrics {
+        Some(metrics) => app.layer(MetricsLayer::new(metrics)),
+        None => app,
+    };
+    let app = app.layer(RequestLogLayer);
 
-    info!("Server running on http://127.0.0.1:{}", port);
-    axum::serve(listener, app).await?;
-
-    Ok(())
+    Ok((app, state))
 }
 
-#[instrument(skip_all, fields(frontend_port = %frontend_port))]
-async fn create_app_state(frontend_port: u16, environment: Environment) -> Result<AppState> {
-    info!("Creating application state");
-    let (_, refresh_frontend) =
-        phantom_frame::create_proxy(create_proxy_config(frontend_port, environment)?);
+/// Waits for every listener to finish; the first failure shuts the others down too.
+async fn join_servers(
+    servers: &mut JoinSet<std::io::Result<()>>,
+    shutdown_tx: &watch::Sender<bool>,
+) -> Result<()> {
+    let mut result = Ok(());
+    while let Some(joined) = servers.join_next().await {
+        let outcome = match joined {
+            Ok(served) => served.context("Listener failed"),
+            Err(e) => Err(e).context("Listener task panicked"),
+        };
+        if let Err(e) = outcome
+            && result.is_ok()
+        {
+            shutdown_tx.send_replace(true);
+            result = Err(e);
+        }
+    }
+
+    result
+}
 
-    Ok(AppState { refresh_frontend })
+/// Clears the proxy cache whenever the supervisor brings the frontend back after a crash,
+/// so pages rendered by the previous process are not served from cache.
+fn spawn_refresh_on_restart(
+    mut frontend_status: watch::Receiver<FrontendStatus>,
+    refresh_frontend: RefreshTrigger,
+) {
+    tokio::spawn(async move {
+        let mut restarts = frontend_status.borrow().restarts;
+        while frontend_status.changed().await.is_ok() {
+            let status = *frontend_status.borrow_and_update();
+            if status.state == FrontendState::Ready && status.restarts > restarts {
+                restarts = status.restarts;
+                info!(restarts, "Frontend restarted, refreshing proxy cache");
+                refresh_frontend.trigger();
+            }
+        }
+    });
 }
 
-#[instrument(skip_all, fields(frontend_port = %frontend_port))]
+#[instrument(skip_all, fields(upstream = %upstream))]
 pub async fn create_proxy_router(
-    frontend_port: u16,
+    upstream: &Upstream,
     environment: Environment,
-) -> Result<Router> {
+    proxy: &ProxyConfig,
+    api_prefix: &str,
+) -> Result<(Router, RefreshTrigger)> {
     info!("Creating proxy router");
-    let proxy_config = create_proxy_config(frontend_port, environment)?;
-    let (proxy_app, _) = phantom_frame::create_proxy(proxy_config);
+    let proxy_config = create_proxy_config(upstream, environment, proxy, api_prefix)?;
+    let (proxy_app, refresh_frontend) = upstream.create_proxy(proxy_config);
+
+    // Only proxied requests get an upstream span, not Rust routes or static assets
+    #[cfg(feature = "otel")]
+    let proxy_app = proxy_app.layer(crate::telemetry::UpstreamTraceLayer);
 
-    Ok(proxy_app)
+    Ok((proxy_app, refresh_frontend))
 }
 
-#[instrument(skip_all, fields(frontend_port = %frontend_port))]
-fn create_proxy_config(frontend_port: u16, environment: Environment) -> Result<CreateProxyConfig> {
+#[instrument(skip_all)]
+fn create_proxy_config(
+    upstream: &Upstream,
+    environment: Environment,
+    proxy: &ProxyConfig,
+    api_prefix: &str,
+) -> Result<CreateProxyConfig> {
     info!("Creating proxy configuration");
-    let proxy_config = CreateProxyConfig::new(format!("http://localhost:{}", frontend_port))
-        .with_cache_key_fn(|req| format!("{}::{}", req.method, req.path))
-        .with_exclude_paths(vec![
-            "POST *".to_string(),
-            "PUT *".to_string(),
-            "DELETE *".to_string(),
-            "PATCH *".to_string(),
-        ])
+    let exclude_paths = cache_exclude_rules(proxy, api_prefix);
+
+    let key_config = proxy.clone();
+    let proxy_config = CreateProxyConfig::new(upstream.to_string())
+        .with_cache_key_fn(move |req| key_config.render_cache_key(req.method, req.path, req.query))
+        .with_exclude_paths(exclude_paths)
         .with_websocket_enabled(matches!(environment, Environment::Development));
 
     Ok(proxy_config)
 }
+
+/// phantom-frame patterns for requests that are always proxied without caching
+pub fn cache_exclude_rules(proxy: &ProxyConfig, api_prefix: &str) -> Vec<String> {
+    proxy
+        .exclude_methods
+        .iter()
+        .map(|method| format!("{} *", method))
+        .chain([
+            format!("{}/*", admin::ADMIN_PREFIX),
+            format!("{}/*", api_prefix),
+        ])
+        .chain(proxy.exclude_paths.iter().cloned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use tower::ServiceExt;
+
+    /// A frontend that answers every request with how many it has served
+    fn counting_frontend() -> (String, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let count = Arc::new(AtomicUsize::new(0));
+        let served = count.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut line = String::new();
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    line.clear();
+                }
+                let body = (served.fetch_add(1, Ordering::SeqCst) + 1).to_string();
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+        (url, count)
+    }
+
+    async fn get(app: &Router) -> String {
+        let request = Request::get("/page").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn refresh_trigger_clears_the_serving_proxy() {
+        let (url, count) = counting_frontend();
+        let config = Config::default();
+        let (app, refresh_frontend) = create_proxy_router(
+            &url.parse().unwrap(),
+            Environment::Production,
+            &config.proxy,
+            &config.server.api_prefix,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(get(&app).await, "1");
+        assert_eq!(
+            get(&app).await,
+            "1",
+            "second request is served from the cache"
+        );
+
+        refresh_frontend.trigger();
+        // The cache is cleared by a background task
+        let mut body = String::new();
+        for _ in 0..50 {
+            body = get(&app).await;
+            if body != "1" {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(body, "2");
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn refresh_from_app_state_clears_the_serving_proxy() {
+        let (url, count) = counting_frontend();
+        let (_status_tx, status) = watch::channel(FrontendStatus {
+            state: FrontendState::Ready,
+            restarts: 0,
+        });
+        let (app, state) = create_app(
+            &Arc::new(Config::default()),
+            &url.parse().unwrap(),
+            FrontendAddress::Tcp {
+                host: "127.0.0.1".into(),
+                port: 0,
+            },
+            Environment::Production,
+            status,
+            None,
+            #[cfg(not(debug_assertions))]
+            crate::embed::AssetsLayer::new(crate::embed::MimeTypes::new([])),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(get(&app).await, "1");
+        assert_eq!(
+            get(&app).await,
+            "1",
+            "second request is served from the cache"
+        );
+
+        state.refresh_frontend.trigger();
+        // The cache is cleared by a background task
+        let mut body = String::new();
+        for _ in 0..50 {
+            body = get(&app).await;
+            if body != "1" {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(body, "2");
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,55 @@
+use tokio::sync::watch;
+use tracing::info;
+
+/// Flips `shutdown_tx` to true once the process receives SIGINT (Ctrl-C) or, on Unix, SIGTERM.
+///
+/// On Unix the handlers are in place when this returns, so a signal that arrives while the
+/// frontend is still starting begins a shutdown instead of killing the server outright.
+pub fn listen(shutdown_tx: watch::Sender<bool>) -> std::io::Result<()> {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut interrupt = signal(SignalKind::interrupt())?;
+        let mut terminate = signal(SignalKind::terminate())?;
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = interrupt.recv() => info!("Received SIGINT, shutting down"),
+                _ = terminate.recv() => info!("Received SIGTERM, shutting down"),
+            }
+            shutdown_tx.send_replace(true);
+        });
+    }
+
+    #[cfg(not(unix))]
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            info!("Received Ctrl-C, shutting down");
+            shutdown_tx.send_replace(true);
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn starts_shutdown_on_sigterm() {
+        let (shutdown_tx, mut shutdown_rx) = watch::channel(false);
+        listen(shutdown_tx).unwrap();
+
+        // SAFETY: raise(3) has no memory-safety preconditions; the handler is installed above
+        unsafe {
+            libc::raise(libc::SIGTERM);
+        }
+        let started = shutdown_rx.wait_for(|started| *started);
+        tokio::time::timeout(Duration::from_secs(5), started)
+            .await
+            .expect("shutdown started")
+            .unwrap();
+    }
+}
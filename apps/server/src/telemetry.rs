@@ -0,0 +1,351 @@
+use anyhow::{Context, Result};
+use axum::{
+    body::Body,
+    http::{HeaderMap, HeaderName, HeaderValue, Request},
+    response::Response,
+};
+use opentelemetry::{
+    global,
+    propagation::{Extractor, Injector},
+    trace::TracerProvider as _,
+};
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{
+    propagation::TraceContextPropagator,
+    trace::{SdkTracer, SdkTracerProvider},
+    Resource,
+};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context as TaskContext, Poll};
+use tower::{Layer, Service};
+use tracing::{field::Empty, info, info_span, Instrument, Span, Subscriber};
+use tracing_opentelemetry::OpenTelemetrySpanExt;
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::{config::TelemetryConfig, proxy_events};
+
+/// Exports `tracing` spans to an OTLP/HTTP collector.
+///
+/// Spans are batched on a background thread; call [`Telemetry::shutdown`] before
+/// exiting so the last batch is flushed.
+pub struct Telemetry {
+    provider: SdkTracerProvider,
+    tracer: SdkTracer,
+}
+
+impl Telemetry {
+    /// Sets up the exporter when `telemetry.otlp_endpoint` is configured
+    pub fn init(config: &TelemetryConfig) -> Result<Option<Self>> {
+        let Some(endpoint) = &config.otlp_endpoint else {
+            return Ok(None);
+        };
+
+        let exporter = SpanExporter::builder()
+            .with_http()
+            .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+            .build()
+            .with_context(|| format!("Failed to create OTLP exporter for {}", endpoint))?;
+        let provider = SdkTracerProvider::builder()
+            .with_batch_exporter(exporter)
+            .with_resource(
+                Resource::builder()
+                    .with_service_name(config.service_name.clone())
+                    .build(),
+            )
+            .build();
+        let tracer = provider.tracer(env!("CARGO_PKG_NAME"));
+
+        // W3C trace context, so the frontend can continue our traces
+        global::set_text_map_propagator(TraceContextPropagator::new());
+
+        Ok(Some(Self { provider, tracer }))
+    }
+
+    /// The `tracing` layer that turns spans into OpenTelemetry spans
+    pub fn layer<S>(&self) -> impl tracing_subscriber::Layer<S>
+    where
+        S: Subscriber + for<'span> LookupSpan<'span>,
+    {
+        tracing_opentelemetry::layer().with_tracer(self.tracer.clone())
+    }
+
+    /// Flushes pending spans and stops the exporter
+    pub async fn shutdown(self) {
+        let provider = self.provider;
+        match tokio::task::spawn_blocking(move || provider.shutdown()).await {
+            Ok(Ok(())) => info!("Trace exporter stopped"),
+            Ok(Err(e)) => tracing::warn!("Failed to flush traces: {}", e),
+            Err(e) => tracing::warn!("Failed to flush traces: {}", e),
+        }
+    }
+}
+
+/// Makes an inbound request span a server span, continuing the caller's trace if it sent `traceparent`
+pub fn continue_trace(span: &Span, request: &Request<Body>) {
+    span.record("otel.name", format!("{} {}", request.method(), request.uri().path()));
+    span.record("otel.kind", "server");
+
+    let parent = global::get_text_map_propagator(|propagator| {
+        propagator.extract(&HeaderExtractor(request.headers()))
+    });
+    // Fails only when the span is disabled, in which case there is nothing to link
+    span.set_parent(parent).ok();
+}
+
+/// Wraps proxied requests in a client span and passes its context to the frontend.
+///
+/// phantom-frame forwards request headers as is, so injecting `traceparent` here
+/// makes the SvelteKit spans children of this one. Cache hits get a span too,
+/// tagged with the outcome, but never reach the frontend.
+#[derive(Clone, Default)]
+pub struct UpstreamTraceLayer;
+
+impl<S> Layer<S> for UpstreamTraceLayer {
+    type Service = UpstreamTraceMiddleware<S>;
+
+    fn layer(&self, inner: S) -> UpstreamTraceMiddleware<S> {
+        UpstreamTraceMiddleware { inner }
+    }
+}
+
+#[derive(Clone)]
+pub struct UpstreamTraceMiddleware<S> {
+    inner: S,
+}
+
+impl<S> Service<Request<Body>> for UpstreamTraceMiddleware<S>
+where
+    S: Service<Request<Body>, Response = Response> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut TaskContext<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Request<Body>) -> Self::Future {
+        let span = info_span!(
+            "proxy",
+            otel.name = format!("proxy {} {}", req.method(), req.uri().path()),
+            otel.kind = "client",
+            cache = Empty,
+            status = Empty,
+        );
+
+        let context = span.context();
+        global::get_text_map_propagator(|propagator| {
+            propagator.inject_context(&context, &mut HeaderInjector(req.headers_mut()))
+        });
+
+        let future = span.in_scope(|| self.inner.call(req));
+        Box::pin(
+            async move {
+                let response = future.await?;
+                let span = Span::current();
+                if let Some(cache) = proxy_events::current().cache {
+                    span.record("cache", cache.as_str());
+                }
+                span.record("status", response.status().as_u16());
+                Ok(response)
+            }
+            .instrument(span),
+        )
+    }
+}
+
+struct HeaderExtractor<'a>(&'a HeaderMap);
+
+impl Extractor for HeaderExtractor<'_> {
+    fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).and_then(|value| value.to_str().ok())
+    }
+
+    fn keys(&self) -> Vec<&str> {
+        self.0.keys().map(HeaderName::as_str).collect()
+    }
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::request_log::RequestLogLayer;
+    use opentelemetry_proto::tonic::collector::trace::v1::ExportTraceServiceRequest;
+    use opentelemetry_proto::tonic::trace::v1::{span::SpanKind, Span as ExportedSpan};
+    use phantom_frame::CreateProxyConfig;
+    use prost::Message;
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::mpsc;
+    use std::time::Duration;
+    use tower::ServiceExt;
+    use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
+
+    struct HttpRequest {
+        target: String,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+    }
+
+    /// Serves keep-alive HTTP/1.1 requests on `listener`, answering each with `response`
+    fn serve(
+        listener: TcpListener,
+        response: &'static str,
+        mut handle: impl FnMut(HttpRequest) + Send + 'static,
+    ) {
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                while let Some(request) = read_request(&mut reader) {
+                    handle(request);
+                    if stream.write_all(response.as_bytes()).is_err() {
+                        break;
+                    }
+                }
+            }
+        });
+    }
+
+    fn read_request(reader: &mut BufReader<TcpStream>) -> Option<HttpRequest> {
+        let mut line = String::new();
+        if reader.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        let target = line.split_whitespace().nth(1)?.to_string();
+
+        let mut headers = Vec::new();
+        loop {
+            line.clear();
+            reader.read_line(&mut line).ok()?;
+            let Some((name, value)) = line.trim_end().split_once(':') else {
+                break;
+            };
+            headers.push((name.to_ascii_lowercase(), value.trim().to_string()));
+        }
+        let length = headers
+            .iter()
+            .find(|(name, _)| name == "content-length")
+            .map_or(0, |(_, value)| value.parse().unwrap());
+        let mut body = vec![0; length];
+        reader.read_exact(&mut body).ok()?;
+
+        Some(HttpRequest {
+            target,
+            headers,
+            body,
+        })
+    }
+
+    /// Stand-in OTLP/HTTP collector that passes on every exported span
+    fn collector() -> (String, mpsc::Receiver<ExportedSpan>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, spans) = mpsc::channel();
+        let response =
+            "HTTP/1.1 200 OK\r\nContent-Type: application/x-protobuf\r\nContent-Length: 0\r\n\r\n";
+        serve(listener, response, move |request| {
+            assert_eq!(request.target, "/v1/traces");
+            let export = ExportTraceServiceRequest::decode(request.body.as_slice()).unwrap();
+            for span in export
+                .resource_spans
+                .into_iter()
+                .flat_map(|resource| resource.scope_spans)
+                .flat_map(|scope| scope.spans)
+            {
+                tx.send(span).ok();
+            }
+        });
+        (url, spans)
+    }
+
+    /// Frontend that passes on the `traceparent` each request arrived with
+    fn frontend() -> (String, mpsc::Receiver<Option<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, traceparents) = mpsc::channel();
+        let response = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok";
+        serve(listener, response, move |request| {
+            let traceparent = request
+                .headers
+                .into_iter()
+                .find(|(name, _)| name == "traceparent")
+                .map(|(_, value)| value);
+            tx.send(traceparent).ok();
+        });
+        (url, traceparents)
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+
+    #[tokio::test]
+    async fn exports_request_and_proxy_spans_and_propagates_the_trace() {
+        let (collector_url, spans) = collector();
+        let (frontend_url, traceparents) = frontend();
+        // The trailing slash must not end up in the export path
+        let config = TelemetryConfig {
+            otlp_endpoint: Some(format!("{}/", collector_url)),
+            ..TelemetryConfig::default()
+        };
+        let telemetry = Telemetry::init(&config).unwrap().unwrap();
+        let guard = tracing_subscriber::registry()
+            .with(telemetry.layer())
+            .set_default();
+
+        let (proxy, _refresh) = phantom_frame::create_proxy(CreateProxyConfig::new(frontend_url));
+        let app = proxy.layer(UpstreamTraceLayer).layer(RequestLogLayer);
+        let caller_trace = "4bf92f3577b34da6a3ce929d0e0e4736";
+        let caller_span = "00f067aa0ba902b7";
+        let traceparent = format!("00-{}-{}-01", caller_trace, caller_span);
+        let request = Request::get("/page")
+            .header("traceparent", traceparent)
+            .body(Body::empty())
+            .unwrap();
+        app.oneshot(request).await.unwrap();
+        drop(guard);
+        telemetry.shutdown().await;
+
+        let traceparent = traceparents
+            .recv_timeout(Duration::from_secs(5))
+            .unwrap()
+            .expect("traceparent sent to the frontend");
+        let spans: Vec<ExportedSpan> = spans.try_iter().collect();
+        let inbound = spans
+            .iter()
+            .find(|span| span.name == "GET /page")
+            .expect("inbound span");
+        let proxy = spans
+            .iter()
+            .find(|span| span.name == "proxy GET /page")
+            .expect("proxy span");
+
+        assert_eq!(inbound.kind, SpanKind::Server as i32);
+        assert_eq!(hex(&inbound.trace_id), caller_trace);
+        assert_eq!(hex(&inbound.parent_span_id), caller_span);
+        assert_eq!(proxy.kind, SpanKind::Client as i32);
+        assert_eq!(proxy.trace_id, inbound.trace_id);
+        assert_eq!(proxy.parent_span_id, inbound.span_id);
+        assert_eq!(
+            traceparent,
+            format!("00-{}-{}-01", caller_trace, hex(&proxy.span_id))
+        );
+    }
+}
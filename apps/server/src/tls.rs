@@ -0,0 +1,421 @@
+use anyhow::{Context, Result};
+use rustls::crypto::CryptoProvider;
+use rustls::server::{ClientHello, ResolvesServerCert};
+use rustls::sign::CertifiedKey;
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock, Weak};
+use std::time::{Duration, SystemTime};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+use tokio::task::JoinHandle;
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::server::TlsStream;
+use tracing::{debug, info, warn};
+
+use crate::config::TlsConfig;
+
+/// Connections that do not finish the TLS handshake in time are dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Completed handshakes waiting to be picked up by the server
+const ACCEPT_QUEUE: usize = 128;
+
+/// Builds the TLS acceptor for `config`, if enabled, and starts watching its certificate files.
+///
+/// Fails if the initial certificate or key cannot be loaded. Later reload
+/// failures are logged and the previous certificate stays in use.
+pub fn acceptor(config: &TlsConfig) -> Result<Option<TlsAcceptor>> {
+    let Some((cert, key)) = config.paths() else {
+        return Ok(None);
+    };
+
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let resolver = Arc::new(ReloadingCert::load(
+        cert.to_path_buf(),
+        key.to_path_buf(),
+        provider.clone(),
+    )?);
+    spawn_reload(Arc::downgrade(&resolver), config.reload_interval());
+
+    let mut server_config = rustls::ServerConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .context("Failed to configure TLS protocol versions")?
+        .with_no_client_auth()
+        .with_cert_resolver(resolver);
+    server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+    Ok(Some(TlsAcceptor::from(Arc::new(server_config))))
+}
+
+/// Certificate resolver that swaps in a new certificate when the files on disk change.
+///
+/// Only new handshakes pick up the new certificate; established connections are unaffected.
+#[derive(Debug)]
+struct ReloadingCert {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    provider: Arc<CryptoProvider>,
+    current: RwLock<Loaded>,
+}
+
+#[derive(Debug)]
+struct Loaded {
+    key: Arc<CertifiedKey>,
+    modified: (Option<SystemTime>, Option<SystemTime>),
+}
+
+impl ReloadingCert {
+    fn load(cert_path: PathBuf, key_path: PathBuf, provider: Arc<CryptoProvider>) -> Result<Self> {
+        let modified = modified_times(&cert_path, &key_path);
+        let key = load_certified_key(&cert_path, &key_path, &provider)?;
+        info!("Loaded TLS certificate from {:?}", cert_path);
+
+        Ok(Self {
+            cert_path,
+            key_path,
+            provider,
+            current: RwLock::new(Loaded {
+                key: Arc::new(key),
+                modified,
+            }),
+        })
+    }
+
+    /// Reloads the certificate if either file's modification time changed
+    fn reload_if_changed(&self) {
+        let modified = modified_times(&self.cert_path, &self.key_path);
+        if self.current.read().expect("cert lock poisoned").modified == modified {
+            return;
+        }
+
+        match load_certified_key(&self.cert_path, &self.key_path, &self.provider) {
+            Ok(key) => {
+                *self.current.write().expect("cert lock poisoned") = Loaded {
+                    key: Arc::new(key),
+                    modified,
+                };
+                info!("Reloaded TLS certificate from {:?}", self.cert_path);
+            }
+            Err(e) => {
+                // Remember the failed version so it is not retried until the files change again
+                self.current.write().expect("cert lock poisoned").modified = modified;
+                warn!(error = %format!("{:#}", e), "Failed to reload TLS certificate, keeping the previous one");
+            }
+        }
+    }
+}
+
+impl ResolvesServerCert for ReloadingCert {
+    fn resolve(&self, _client_hello: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        Some(self.current.read().ok()?.key.clone())
+    }
+}
+
+/// Polls the certificate files until the resolver is dropped
+fn spawn_reload(resolver: Weak<ReloadingCert>, interval: Duration) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let Some(resolver) = resolver.upgrade() else {
+                return;
+            };
+            tokio::task::spawn_blocking(move || resolver.reload_if_changed())
+                .await
+                .ok();
+        }
+    });
+}
+
+fn modified_times(cert: &Path, key: &Path) -> (Option<SystemTime>, Option<SystemTime>) {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    (modified(cert), modified(key))
+}
+
+fn load_certified_key(cert: &Path, key: &Path, provider: &CryptoProvider) -> Result<CertifiedKey> {
+    let certs = CertificateDer::pem_file_iter(cert)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| anyhow::anyhow!("Failed to read certificate chain {:?}: {}", cert, e))?;
+    if certs.is_empty() {
+        anyhow::bail!("No certificates found in {:?}", cert);
+    }
+
+    let private_key = PrivateKeyDer::from_pem_file(key)
+        .map_err(|e| anyhow::anyhow!("Failed to read private key {:?}: {}", key, e))?;
+
+    CertifiedKey::from_der(certs, private_key, provider)
+        .with_context(|| format!("Certificate {:?} does not match key {:?}", cert, key))
+}
+
+/// TCP listener that hands out connections once their TLS handshake has completed.
+///
+/// Handshakes run on their own tasks so a slow client cannot hold up other connections.
+pub struct TlsListener {
+    local_addr: SocketAddr,
+    handshakes: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+    accept_task: JoinHandle<()>,
+    stop: watch::Sender<bool>,
+}
+
+impl TlsListener {
+    pub fn new(listener: TcpListener, acceptor: TlsAcceptor) -> std::io::Result<Self> {
+        let local_addr = listener.local_addr()?;
+        let (tx, handshakes) = mpsc::channel(ACCEPT_QUEUE);
+        let (stop, stopped) = watch::channel(false);
+        let accept_task = tokio::spawn(accept_loop(listener, acceptor, tx, stopped));
+
+        Ok(Self {
+            local_addr,
+            handshakes,
+            accept_task,
+            stop,
+        })
+    }
+
+    /// Returns a function that closes the port and stops handshaking, for use once
+    /// shutdown starts; connections that were already handed out are unaffected
+    pub fn stopper(&self) -> impl FnOnce() + Send + 'static {
+        let stop = self.stop.clone();
+        move || {
+            stop.send_replace(true);
+        }
+    }
+}
+
+impl Drop for TlsListener {
+    fn drop(&mut self) {
+        self.accept_task.abort();
+    }
+}
+
+impl axum::serve::Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.handshakes.recv().await {
+            Some(connection) => connection,
+            // The accept loop has been stopped for shutdown
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+async fn accept_loop(
+    listener: TcpListener,
+    acceptor: TlsAcceptor,
+    tx: mpsc::Sender<(TlsStream<TcpStream>, SocketAddr)>,
+    mut stopped: watch::Receiver<bool>,
+) {
+    loop {
+        let accepted = tokio::select! {
+            accepted = listener.accept() => accepted,
+            _ = stopped.wait_for(|stopped| *stopped) => {
+                debug!("Stopped accepting TLS connections");
+                return;
+            }
+        };
+        let (stream, peer) = match accepted {
+            Ok(connection) => connection,
+            Err(e) if is_connection_error(&e) => continue,
+            Err(e) => {
+                warn!(error = %e, "Failed to accept TLS connection");
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+
+        let acceptor = acceptor.clone();
+        let tx = tx.clone();
+        let mut stopped = stopped.clone();
+        tokio::spawn(async move {
+            let handshake = tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream));
+            let handshake = tokio::select! {
+                handshake = handshake => handshake,
+                _ = stopped.wait_for(|stopped| *stopped) => return,
+            };
+            match handshake {
+                Ok(Ok(stream)) => {
+                    tx.send((stream, peer)).await.ok();
+                }
+                Ok(Err(e)) => debug!(%peer, error = %e, "TLS handshake failed"),
+                Err(_) => debug!(%peer, "TLS handshake timed out"),
+            }
+        });
+    }
+}
+
+fn is_connection_error(e: &std::io::Error) -> bool {
+    matches!(
+        e.kind(),
+        std::io::ErrorKind::ConnectionRefused
+            | std::io::ErrorKind::ConnectionAborted
+            | std::io::ErrorKind::ConnectionReset
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::listen::BoundListener;
+    use axum::Router;
+    use rustls::pki_types::ServerName;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio_rustls::{TlsConnector, client};
+
+    /// A self-signed certificate for localhost, written to `dir`
+    fn write_cert(dir: &Path) -> CertificateDer<'static> {
+        let generated = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+        std::fs::write(dir.join("cert.pem"), generated.cert.pem()).unwrap();
+        std::fs::write(dir.join("key.pem"), generated.signing_key.serialize_pem()).unwrap();
+        generated.cert.der().clone()
+    }
+
+    /// Serves a router that answers every request on a TLS listener for the files in `dir`
+    async fn serve(dir: &Path) -> (SocketAddr, watch::Sender<bool>) {
+        let config = TlsConfig {
+            cert: Some(dir.join("cert.pem")),
+            key: Some(dir.join("key.pem")),
+            reload_interval_secs: 1,
+        };
+        let acceptor = acceptor(&config).unwrap().unwrap();
+        let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let listener = TlsListener::new(tcp, acceptor).unwrap();
+        let addr = listener.local_addr;
+
+        let (shutdown, mut shutdown_rx) = watch::channel(false);
+        let app = Router::new().fallback(|| async { "hello" });
+        let signal = async move {
+            shutdown_rx.wait_for(|started| *started).await.ok();
+        };
+        tokio::spawn(BoundListener::Tls(listener).serve(app, signal));
+        (addr, shutdown)
+    }
+
+    async fn connect(
+        addr: SocketAddr,
+        trusted: &[&CertificateDer<'static>],
+        alpn: &[&[u8]],
+    ) -> std::io::Result<client::TlsStream<TcpStream>> {
+        let mut roots = rustls::RootCertStore::empty();
+        for cert in trusted {
+            roots.add((*cert).clone()).unwrap();
+        }
+        let mut config = rustls::ClientConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .unwrap()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+        config.alpn_protocols = alpn.iter().map(|protocol| protocol.to_vec()).collect();
+
+        let stream = TcpStream::connect(addr).await?;
+        let name = ServerName::try_from("localhost").unwrap();
+        let connector = TlsConnector::from(Arc::new(config));
+        connector.connect(name, stream).await
+    }
+
+    /// Sends a keep-alive HTTP/1.1 request and reads the response
+    async fn get(stream: &mut client::TlsStream<TcpStream>) -> String {
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .await
+            .unwrap();
+        let mut response = Vec::new();
+        while !response.ends_with(b"hello") {
+            let mut chunk = [0; 1024];
+            let read = stream.read(&mut chunk).await.unwrap();
+            assert!(read > 0, "connection closed");
+            response.extend_from_slice(&chunk[..read]);
+        }
+        String::from_utf8(response).unwrap()
+    }
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("tls-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir(&dir).unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn negotiates_h2() {
+        let dir = temp_dir();
+        let cert = write_cert(&dir);
+        let (addr, _shutdown) = serve(&dir).await;
+
+        let alpn: [&[u8]; 2] = [b"h2", b"http/1.1"];
+        let stream = connect(addr, &[&cert], &alpn).await.unwrap();
+        assert_eq!(stream.get_ref().1.alpn_protocol(), Some(&b"h2"[..]));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn new_handshakes_use_a_rewritten_cert() {
+        let dir = temp_dir();
+        let old_cert = write_cert(&dir);
+        let (addr, _shutdown) = serve(&dir).await;
+        let mut open = connect(addr, &[&old_cert], &[b"http/1.1"]).await.unwrap();
+        assert!(get(&mut open).await.starts_with("HTTP/1.1 200"));
+
+        let new_cert = write_cert(&dir);
+        let reloaded = async {
+            loop {
+                let stream = connect(addr, &[&old_cert, &new_cert], &[]).await.unwrap();
+                if stream.get_ref().1.peer_certificates() == Some(&[new_cert.clone()][..]) {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), reloaded)
+            .await
+            .expect("new certificate served");
+
+        // The connection made with the old certificate keeps working
+        assert!(get(&mut open).await.starts_with("HTTP/1.1 200"));
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[tokio::test]
+    async fn stopping_closes_the_port_while_the_listener_lives() {
+        let dir = temp_dir();
+        let cert = write_cert(&dir);
+        let config = TlsConfig {
+            cert: Some(dir.join("cert.pem")),
+            key: Some(dir.join("key.pem")),
+            ..TlsConfig::default()
+        };
+        let acceptor = acceptor(&config).unwrap().unwrap();
+        let tcp = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let mut listener = TlsListener::new(tcp, acceptor).unwrap();
+        let addr = listener.local_addr;
+
+        let accepted = tokio::spawn(async move {
+            let connection = axum::serve::Listener::accept(&mut listener).await;
+            (listener, connection)
+        });
+        connect(addr, &[&cert], &[]).await.unwrap();
+        let (listener, _connection) = accepted.await.unwrap();
+
+        listener.stopper()();
+        let closed = async {
+            while TcpStream::connect(addr).await.is_ok() {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+        };
+        tokio::time::timeout(Duration::from_secs(5), closed)
+            .await
+            .expect("port closed");
+        drop(listener);
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,227 @@
+use anyhow::Result;
+use axum::{http::Uri, Router};
+use phantom_frame::{cache::RefreshTrigger, CreateProxyConfig};
+use std::fmt;
+use std::str::FromStr;
+#[cfg(unix)]
+use std::path::PathBuf;
+
+/// Where the proxy sends requests for the frontend
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Upstream {
+    /// Base URL of an HTTP server, e.g. `http://localhost:5173`
+    Http(String),
+    /// HTTP server on a Unix socket, written `unix:/path/to.sock`
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl FromStr for Upstream {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            if !path.starts_with('/') {
+                anyhow::bail!("must be unix: followed by an absolute socket path, got {:?}", s);
+            }
+            #[cfg(unix)]
+            return Ok(Upstream::Unix(path.into()));
+            #[cfg(not(unix))]
+            anyhow::bail!("{:?} is a Unix socket, which is only supported on Unix", s);
+        }
+
+        let uri: Uri = s
+            .parse()
+            .map_err(|_| anyhow::anyhow!("{:?} is not a valid URL", s))?;
+        if !matches!(uri.scheme_str(), Some("http" | "https")) || uri.host().is_none() {
+            anyhow::bail!("must be an http(s) URL with a host or unix:/path/to.sock, got {:?}", s);
+        }
+
+        Ok(Upstream::Http(s.trim_end_matches('/').to_string()))
+    }
+}
+
+impl fmt::Display for Upstream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Upstream::Http(url) => f.write_str(url),
+            #[cfg(unix)]
+            Upstream::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+impl Upstream {
+    /// The proxy router for this upstream and the trigger that clears its cache.
+    ///
+    /// phantom-frame reaches HTTP upstreams itself, using `proxy_url` from the
+    /// config; for sockets it is given a connector that opens the socket instead.
+    pub fn create_proxy(&self, config: CreateProxyConfig) -> (Router, RefreshTrigger) {
+        match self {
+            Upstream::Http(_) => phantom_frame::create_proxy(config),
+            #[cfg(unix)]
+            Upstream::Unix(socket) => {
+                let socket = socket.clone();
+                phantom_frame::create_proxy(
+                    config.with_connector(move || tokio::net::UnixStream::connect(socket.clone())),
+                )
+            }
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use crate::proxy_events::{self, CacheOutcome, ProxyEventLayer};
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode, header},
+    };
+    use std::io::{BufRead, BufReader, Write};
+    use std::os::unix::net::UnixListener;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use tower::ServiceExt;
+    use tracing_subscriber::{Layer, layer::SubscriberExt, util::SubscriberInitExt};
+
+    /// Answers every request with the number of requests it has seen so far and
+    /// the `Host` it was sent; upgrade requests are accepted with 101
+    fn counting_server(socket: &Path) -> Arc<AtomicUsize> {
+        let listener = UnixListener::bind(socket).unwrap();
+        let count = Arc::new(AtomicUsize::new(0));
+        let served = count.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let (mut line, mut host, mut upgrade) = (String::new(), String::new(), false);
+                while reader.read_line(&mut line).unwrap() > 2 {
+                    let lower = line.to_ascii_lowercase();
+                    if let Some(value) = lower.strip_prefix("host:") {
+                        host = value.trim().to_string();
+                    }
+                    upgrade |= lower.starts_with("upgrade:");
+                    line.clear();
+                }
+                if upgrade {
+                    write!(
+                        stream,
+                        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\r\n"
+                    )
+                    .unwrap();
+                    continue;
+                }
+                let body = format!("{} {}", served.fetch_add(1, Ordering::SeqCst) + 1, host);
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+        count
+    }
+
+    async fn send(router: &Router, method: &str) -> (StatusCode, String, Option<CacheOutcome>) {
+        let request = Request::builder()
+            .method(method)
+            .uri("/page?q=1")
+            .header(header::HOST, "example.com")
+            .body(Body::empty())
+            .unwrap();
+        let (response, outcome) = proxy_events::track(async {
+            let response = router.clone().oneshot(request).await.unwrap();
+            (response, proxy_events::current())
+        })
+        .await;
+        let status = response.status();
+        assert!(response.headers().get(header::CONNECTION).is_none());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (
+            status,
+            String::from_utf8(body.to_vec()).unwrap(),
+            outcome.cache,
+        )
+    }
+
+    #[tokio::test]
+    async fn proxies_and_caches_over_the_socket() {
+        let _guard = tracing_subscriber::registry()
+            .with(ProxyEventLayer.with_filter(ProxyEventLayer::filter()))
+            .set_default();
+        let dir =
+            std::env::temp_dir().join(format!("upstream-test-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir(&dir).unwrap();
+        let socket = dir.join("frontend.sock");
+        let count = counting_server(&socket);
+
+        let upstream = Upstream::Unix(socket.clone());
+        let config = CreateProxyConfig::new(upstream.to_string())
+            .with_exclude_paths(vec!["POST *".to_string()]);
+        let (router, refresh) = upstream.create_proxy(config);
+
+        let ok = |body: &str, cache| (StatusCode::OK, body.to_string(), Some(cache));
+        assert_eq!(
+            send(&router, "GET").await,
+            ok("1 localhost", CacheOutcome::Miss)
+        );
+        assert_eq!(
+            send(&router, "GET").await,
+            ok("1 localhost", CacheOutcome::Hit)
+        );
+        assert_eq!(
+            send(&router, "POST").await,
+            ok("2 localhost", CacheOutcome::Bypass)
+        );
+
+        refresh.trigger();
+        let mut refreshed = None;
+        for _ in 0..50 {
+            let (_, body, cache) = send(&router, "GET").await;
+            if cache == Some(CacheOutcome::Miss) {
+                refreshed = Some(body);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(refreshed.as_deref(), Some("3 localhost"));
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+        let (status, _, cache) = send(&router, "POST").await;
+        assert_eq!(
+            (status, cache),
+            (StatusCode::BAD_GATEWAY, Some(CacheOutcome::Bypass))
+        );
+    }
+
+    #[tokio::test]
+    async fn forwards_upgrades_over_the_socket() {
+        let dir =
+            std::env::temp_dir().join(format!("upstream-test-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::create_dir(&dir).unwrap();
+        let socket = dir.join("frontend.sock");
+        counting_server(&socket);
+
+        let upstream = Upstream::Unix(socket);
+        let (router, _refresh) =
+            upstream.create_proxy(CreateProxyConfig::new(upstream.to_string()));
+        let request = Request::get("/socket")
+            .header(header::CONNECTION, "Upgrade")
+            .header(header::UPGRADE, "websocket")
+            .body(Body::empty())
+            .unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SWITCHING_PROTOCOLS);
+        assert_eq!(response.headers()[header::UPGRADE], "websocket");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
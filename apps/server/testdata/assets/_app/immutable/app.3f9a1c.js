@@ -0,0 +1 @@
+export const start = () => console.log("started");